@@ -0,0 +1,36 @@
+//! Check xtc and trr files, like `gmx check -f`.
+//!
+//! Usage: `xdrcheck FILE...`
+//!
+//! Prints a report for each file. Exits with status 1 if any file couldn't be read in full or
+//! has an inconsistent number of atoms, or 2 if any file couldn't be checked at all.
+
+use std::process::ExitCode;
+
+use xdrfile::prelude::*;
+
+fn main() -> ExitCode {
+    let paths: Vec<_> = std::env::args_os().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("Usage: xdrcheck FILE...");
+        return ExitCode::from(2)
+    }
+    let mut status = 0;
+    for path in paths {
+        let report = XDRFile::<access_mode::Read>::open_path(&path)
+            .and_then(|mut file| file.check_trajectory());
+        match report {
+            Ok(report) => {
+                println!("{}:\n{}", path.to_string_lossy(), report);
+                if !report.is_ok() {
+                    status = status.max(1);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path.to_string_lossy(), e);
+                status = 2;
+            }
+        }
+    }
+    ExitCode::from(status)
+}
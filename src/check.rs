@@ -0,0 +1,349 @@
+use std::{
+    fmt,
+    ffi::{c_int, c_float, c_double},
+    io::SeekFrom,
+};
+
+use crate::{
+    Error, XDRFile, access_mode,
+    frames::read_header,
+    recover::SkippedBytes,
+    trr::{self, TRRHeader, trr_header},
+    xdr::*,
+    xtc::*,
+};
+
+/// Format of a trajectory file, identified by the magic number of its first frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrajectoryFormat {
+    Xtc,
+    Trr,
+}
+
+/// Time step between two frames which differs from the first time step in the file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeGap {
+    /// Index of the frame after the gap, counting the frames which could be read
+    pub frame: usize,
+    /// Time of the frame after the gap
+    pub time: c_float,
+    /// Time since the previous frame
+    pub dt: c_float,
+}
+
+/// Frame with a different number of atoms from the frame before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NatomsChange {
+    /// Index of the frame, counting the frames which could be read
+    pub frame: usize,
+    pub natoms: usize,
+}
+
+/// Summary of a trajectory file and any problems found in it, similar to the output of
+/// `gmx check`. See [`XDRFile::check_trajectory`].
+#[derive(Debug)]
+pub struct CheckReport {
+    pub format: TrajectoryFormat,
+    /// Size of the file in bytes
+    pub file_size: u64,
+    /// Number of frames which could be read
+    pub frames: usize,
+    /// Number of atoms in the first frame, or `None` if it couldn't be read
+    pub natoms: Option<usize>,
+    pub first_time: Option<c_float>,
+    pub last_time: Option<c_float>,
+    /// Time between the first two frames
+    pub timestep: Option<c_float>,
+    /// Time steps which differ from `timestep` by more than 1%, including those across
+    /// unreadable data
+    pub time_gaps: Vec<TimeGap>,
+    pub natoms_changes: Vec<NatomsChange>,
+    /// Number of frames whose box differs from the previous frame with a box
+    pub box_changes: usize,
+    /// Distinct precisions of the compressed coordinates of xtc frames, in the order they
+    /// first appear. Frames with 9 atoms or fewer, which are stored uncompressed, are not
+    /// included.
+    pub precisions: Vec<c_float>,
+    /// Byte offset of a frame at the end of the file which is cut short
+    pub truncated: Option<u64>,
+    /// Data which couldn't be read as frames, other than a truncated final frame
+    pub unreadable: Vec<SkippedBytes>,
+}
+
+impl CheckReport {
+    /// Whether the file can be read in full with a consistent number of atoms. Irregular time
+    /// steps and box changes are not counted as problems.
+    pub fn is_ok(&self) -> bool {
+        self.truncated.is_none() && self.unreadable.is_empty() && self.natoms_changes.is_empty()
+    }
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = match self.format {
+            TrajectoryFormat::Xtc => "xtc",
+            TrajectoryFormat::Trr => "trr",
+        };
+        writeln!(f, "{} file, {} bytes", format, self.file_size)?;
+        writeln!(f, "Frames: {}", self.frames)?;
+        if let Some(natoms) = self.natoms {
+            writeln!(f, "Atoms: {}", natoms)?;
+        }
+        if let (Some(first), Some(last)) = (self.first_time, self.last_time) {
+            writeln!(f, "Time: {} to {} ps", first, last)?;
+        }
+        if let Some(dt) = self.timestep {
+            writeln!(f, "Timestep: {} ps", dt)?;
+        }
+        for gap in &self.time_gaps {
+            writeln!(f, "Irregular timestep of {} ps before frame {} at t = {} ps", gap.dt, gap.frame, gap.time)?;
+        }
+        for change in &self.natoms_changes {
+            writeln!(f, "Number of atoms changes to {} in frame {}", change.natoms, change.frame)?;
+        }
+        if self.box_changes > 0 {
+            writeln!(f, "Box changes in {} frames", self.box_changes)?;
+        }
+        if !self.precisions.is_empty() {
+            let precisions: Vec<_> = self.precisions.iter().map(|p| p.to_string()).collect();
+            writeln!(f, "Precision: {}", precisions.join(", "))?;
+        }
+        if let Some(offset) = self.truncated {
+            writeln!(f, "Truncated frame at byte offset {}", offset)?;
+        }
+        for skipped in &self.unreadable {
+            writeln!(f, "Unreadable bytes {}..{}: {}", skipped.range.start, skipped.range.end, skipped.error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Information about a frame, read while checking it
+struct FrameInfo {
+    natoms: usize,
+    time: c_float,
+    sim_box: Option<matrix>,
+    prec: Option<c_float>,
+}
+
+impl XDRFile<access_mode::Read> {
+    /// Read every frame of an xtc or trr file, which must be seekable, and report on its
+    /// contents and any problems found, like `gmx check`. The format is identified from the
+    /// magic number at the start of the file, and the current position is preserved.
+    ///
+    /// Coordinates of xtc frames are decompressed, but not stored, to check they are intact.
+    /// Unreadable data is skipped by searching byte by byte for the next frame, in the same way as
+    /// [`XDRFile::recover_frames`], except that frames may have any number of atoms.
+    ///
+    /// Errors are only returned for problems with the file as a whole, such as being unable to
+    /// seek or get its size.
+    pub fn check_trajectory(&mut self) -> Result<CheckReport, Error> {
+        let file_size = self.handle.stream_len()?;
+        let format = self.peek_start(|xd| {
+            let mut magic = [0];
+            match xd.read_int(&mut magic) {
+                1 if is_magic(magic[0]) => Ok(TrajectoryFormat::Xtc),
+                1 if magic[0] == trr::MAGIC => Ok(TrajectoryFormat::Trr),
+                1 => Err(Error::from(XDRStatus::exdrMAGIC).at(Some(0), Some(0))),
+                _ => Err(Error::from(XDRStatus::exdrENDOFFILE).at(None, Some(0))),
+            }
+        });
+        let format = match format {
+            Ok(format) => format,
+            // An empty file has no frames in either format
+            Err(e) if e.is_eof() => TrajectoryFormat::Xtc,
+            Err(e) => return Err(e),
+        };
+        let natoms = match format {
+            TrajectoryFormat::Xtc => self.read_xtc_natoms(),
+            TrajectoryFormat::Trr => self.read_trr_natoms(),
+        };
+        let mut report = CheckReport {
+            format,
+            file_size,
+            frames: 0,
+            natoms: natoms.ok(),
+            first_time: None,
+            last_time: None,
+            timestep: None,
+            time_gaps: Vec::new(),
+            natoms_changes: Vec::new(),
+            box_changes: 0,
+            precisions: Vec::new(),
+            truncated: None,
+            unreadable: Vec::new(),
+        };
+        self.peek_start(|xd| {
+            let mut prev_natoms = None;
+            let mut prev_box = None;
+            let mut offset = 0;
+            while offset < file_size {
+                let frame = match check_frame(xd, format, offset, file_size) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        let error = Error::from(e).at(None, Some(offset));
+                        let next = resync(xd, format, offset + 1, file_size, None)?;
+                        let starts_frame = seek_to(xd, offset).is_ok() && starts_with_magic(xd, format);
+                        match next {
+                            None if starts_frame => report.truncated = Some(offset),
+                            _ => {
+                                let end = next.unwrap_or(file_size);
+                                report.unreadable.push(SkippedBytes { range: offset..end, error });
+                            }
+                        }
+                        let Some(next) = next else { break };
+                        offset = next;
+                        continue
+                    }
+                };
+                offset = xd.position();
+
+                let n = report.frames;
+                if let Some(prev) = report.last_time {
+                    let dt = frame.time - prev;
+                    match report.timestep {
+                        None => report.timestep = Some(dt),
+                        Some(timestep) if (dt - timestep).abs() > 0.01 * timestep.abs() => {
+                            report.time_gaps.push(TimeGap { frame: n, time: frame.time, dt });
+                        }
+                        Some(_) => (),
+                    }
+                }
+                report.first_time.get_or_insert(frame.time);
+                report.last_time = Some(frame.time);
+                if prev_natoms.is_some_and(|natoms| natoms != frame.natoms) {
+                    report.natoms_changes.push(NatomsChange { frame: n, natoms: frame.natoms });
+                }
+                prev_natoms = Some(frame.natoms);
+                if let Some(sim_box) = frame.sim_box {
+                    if prev_box.is_some_and(|prev| prev != sim_box) {
+                        report.box_changes += 1;
+                    }
+                    prev_box = Some(sim_box);
+                }
+                if let Some(prec) = frame.prec {
+                    if !report.precisions.contains(&prec) {
+                        report.precisions.push(prec);
+                    }
+                }
+                report.frames += 1;
+            }
+            Ok(())
+        })?;
+        Ok(report)
+    }
+}
+
+/// Seek to `offset`, keeping the read buffer if it is close to the current position
+pub(crate) fn seek_to(xd: &mut XDRFILE, offset: u64) -> Result<(), XDRStatus> {
+    match xd.seek(SeekFrom::Current(offset.wrapping_sub(xd.position()) as i64)) {
+        XDRStatus::exdrOK => Ok(()),
+        e => Err(e),
+    }
+}
+
+/// Whether the next int is the magic number of `format`
+fn starts_with_magic(xd: &mut XDRFILE, format: TrajectoryFormat) -> bool {
+    let mut magic = [0];
+    xd.read_int(&mut magic) == 1 && match format {
+        TrajectoryFormat::Xtc => is_magic(magic[0]),
+        TrajectoryFormat::Trr => magic[0] == trr::MAGIC,
+    }
+}
+
+/// Read the frame starting at `offset`, which must end within the file
+fn check_frame(xd: &mut XDRFILE, format: TrajectoryFormat, offset: u64, file_size: u64) -> Result<FrameInfo, XDRStatus> {
+    seek_to(xd, offset)?;
+    match format {
+        TrajectoryFormat::Xtc => {
+            // Check the frame fits in the file before decompressing it
+            let header = read_header(xd, Some(file_size))?;
+            let end = xd.position();
+            // Skip the magic number, number of atoms, step, time and box
+            seek_to(xd, offset + (4 + DIM * DIM) as u64 * 4)?;
+            let Ok(natoms) = c_int::try_from(header.natoms) else { return Err(XDRStatus::exdrHEADER) };
+            let mut ncoord = natoms;
+            let mut prec: c_float = 0.;
+            if xd.decompress_coord_each(&mut ncoord, &mut prec, header.natoms, |_, _| ()) != natoms
+                || xd.position() > end
+            {
+                return Err(XDRStatus::exdr3DX)
+            }
+            seek_to(xd, end)?;
+            Ok(FrameInfo {
+                natoms: header.natoms,
+                time: header.time,
+                sim_box: Some(header.sim_box),
+                prec: (header.natoms > 9).then_some(header.prec),
+            })
+        }
+        TrajectoryFormat::Trr => {
+            let mut sh = TRRHeader::default();
+            match trr_header(xd, &mut sh, true) {
+                XDRStatus::exdrOK => (),
+                e => return Err(e),
+            }
+            let Ok(natoms) = usize::try_from(sh.natoms) else { return Err(XDRStatus::exdrHEADER) };
+            // Only the blocks that `read_trr_data` reads may be present, with the sizes it reads
+            if [sh.ir_size, sh.e_size, sh.top_size, sh.sym_size].iter().any(|&size| size != 0) {
+                return Err(XDRStatus::exdrHEADER)
+            }
+            let real_size = if sh.is_double { size_of::<c_double>() } else { size_of::<c_float>() };
+            let matrix_bytes = (DIM * DIM * real_size) as u64;
+            let ncoord_bytes = natoms as u64 * (DIM * real_size) as u64;
+            let blocks = [
+                (sh.box_size, matrix_bytes), (sh.vir_size, matrix_bytes), (sh.pres_size, matrix_bytes),
+                (sh.x_size, ncoord_bytes), (sh.v_size, ncoord_bytes), (sh.f_size, ncoord_bytes),
+            ];
+            if blocks.iter().any(|&(size, expected)| size != 0 && u64::try_from(size) != Ok(expected)) {
+                return Err(XDRStatus::exdrHEADER)
+            }
+            let data_size: u64 = blocks.iter().map(|&(size, _)| size as u64).sum();
+            let end = xd.position() + data_size;
+            if end > file_size {
+                return Err(XDRStatus::exdr3DX)
+            }
+            let sim_box = if sh.box_size != 0 {
+                let mut sim_box = matrix::new();
+                let values = sim_box.0.as_flattened_mut();
+                if sh.is_double {
+                    let mut d = [0 as c_double; DIM * DIM];
+                    if xd.read_double(&mut d) != d.len() { return Err(XDRStatus::exdrDOUBLE) }
+                    for (v, d) in values.iter_mut().zip(d) {
+                        *v = d as c_float;
+                    }
+                } else if xd.read_float(values) != values.len() {
+                    return Err(XDRStatus::exdrFLOAT)
+                }
+                Some(sim_box)
+            } else {
+                None
+            };
+            seek_to(xd, end)?;
+            Ok(FrameInfo { natoms, time: sh.t, sim_box, prec: None })
+        }
+    }
+}
+
+/// Search forwards from `offset` for the next frame which can be read, which contains `natoms`
+/// atoms if it is given, and which is followed by the end of the file or another magic number.
+/// Returns its offset, or `None` if there isn't one.
+pub(crate) fn resync(
+    xd: &mut XDRFILE,
+    format: TrajectoryFormat,
+    mut offset: u64,
+    file_size: u64,
+    natoms: Option<usize>
+) -> Result<Option<u64>, Error> {
+    while offset + 4 <= file_size {
+        seek_to(xd, offset)?;
+        if starts_with_magic(xd, format)
+            && check_frame(xd, format, offset, file_size).is_ok_and(|frame| natoms.is_none_or(|n| n == frame.natoms))
+            && (xd.position() == file_size || starts_with_magic(xd, format))
+        {
+            return Ok(Some(offset))
+        }
+        offset += 1;
+    }
+    Ok(None)
+}
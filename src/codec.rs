@@ -0,0 +1,347 @@
+//! Traits for types which can be written to and read from xdr files, field by field.
+//!
+//! With the `derive` feature, [`XdrEncode`] and [`XdrDecode`] can be derived for structs and
+//! enums. Fields are stored in order, and enums are stored as their discriminant as an int,
+//! followed by the fields of the variant. Each field is stored according to its type:
+//! - `bool`, `i8`, `i16`, `i32`, `u8`, `u16` and `u32` as 4-byte (unsigned) ints, and 64-bit
+//!   integers as 8-byte hypers
+//! - `f32` and `f64` as floats and doubles
+//! - `String` and `Vec` as their length as an unsigned int followed by their elements, and
+//!   arrays as their elements
+//! - `Option` as a bool followed by the value if it is present
+//!
+//! The storage of a field can be changed with the `xdr` attribute:
+//! - `#[xdr(fixed = N)]` stores a `Vec` with exactly `N` elements and no length
+//! - `#[xdr(opaque)]` stores a `Vec<u8>` or `[u8; N]` as opaque data, packed into 4-byte words
+//!   rather than using an int per byte. This can be combined with `fixed` for a `Vec<u8>`.
+//! - `#[xdr(pad = N)]` adds `N` bytes of zero padding after the field, rounded up to a multiple
+//!   of 4 bytes, which are skipped when reading
+//! - `#[xdr(skip)]` doesn't store the field, and uses its default value when reading
+//!
+//! For example, the header at the start of each frame of a trr file:
+//! ```
+//! # #[cfg(feature = "derive")] {
+//! use xdrfile::codec::{XdrDecode, XdrEncode};
+//!
+//! #[derive(XdrEncode, XdrDecode)]
+//! struct TrrHeader {
+//!     magic: i32,
+//!     version_len: i32,
+//!     version: String,
+//!     block_sizes: [i32; 10],
+//!     natoms: i32,
+//!     step: i32,
+//!     nre: i32,
+//!     t: f32,
+//!     lambda: f32,
+//! }
+//! # }
+//! ```
+
+use std::ffi::{c_int, c_uint};
+
+use crate::{
+    Error, ErrorKind, MAX_PREALLOC, XDRAccessMode, XDRFile, access_mode,
+    xdr::XDRStatus,
+};
+
+#[cfg(feature = "derive")]
+pub use xdrfile_derive::{XdrDecode, XdrEncode};
+
+/// A type which can be written to an xdr file
+pub trait XdrEncode {
+    fn encode<MODE: XDRAccessMode + access_mode::Writable>(&self, file: &mut XDRFile<MODE>) -> Result<(), Error>;
+}
+
+/// A type which can be read from an xdr file
+pub trait XdrDecode: Sized {
+    fn decode(file: &mut XDRFile<access_mode::Read>) -> Result<Self, Error>;
+}
+
+impl<MODE: XDRAccessMode + access_mode::Writable> XDRFile<MODE> {
+    /// Write a value which implements [`XdrEncode`]
+    pub fn encode<T: XdrEncode + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        value.encode(self)
+    }
+}
+
+impl XDRFile<access_mode::Read> {
+    /// Read a value which implements [`XdrDecode`]
+    pub fn decode<T: XdrDecode>(&mut self) -> Result<T, Error> {
+        T::decode(self)
+    }
+}
+
+/// Implement the traits for a type with a pair of primitive methods on `XDRFile`
+macro_rules! xdr_codec {
+    ($t:ty, $read:ident, $write:ident) => {
+        impl XdrEncode for $t {
+            fn encode<MODE: XDRAccessMode + access_mode::Writable>(&self, file: &mut XDRFile<MODE>) -> Result<(), Error> {
+                file.$write(&[*self])
+            }
+        }
+
+        impl XdrDecode for $t {
+            fn decode(file: &mut XDRFile<access_mode::Read>) -> Result<Self, Error> {
+                let mut v = [Default::default()];
+                file.$read(&mut v)?;
+                Ok(v[0])
+            }
+        }
+    };
+}
+
+xdr_codec!(u8, read_uchar, write_uchar);
+xdr_codec!(i16, read_short, write_short);
+xdr_codec!(u16, read_ushort, write_ushort);
+xdr_codec!(i32, read_int, write_int);
+xdr_codec!(u32, read_uint, write_uint);
+xdr_codec!(f32, read_float, write_float);
+xdr_codec!(f64, read_double, write_double);
+
+// `c_char` isn't always signed, so `i8` goes through an int
+impl XdrEncode for i8 {
+    fn encode<MODE: XDRAccessMode + access_mode::Writable>(&self, file: &mut XDRFile<MODE>) -> Result<(), Error> {
+        file.write_int(&[(*self).into()])
+    }
+}
+
+impl XdrDecode for i8 {
+    fn decode(file: &mut XDRFile<access_mode::Read>) -> Result<Self, Error> {
+        i32::decode(file)?.try_into().map_err(|_| ErrorKind::Overflow.into())
+    }
+}
+
+impl XdrEncode for bool {
+    fn encode<MODE: XDRAccessMode + access_mode::Writable>(&self, file: &mut XDRFile<MODE>) -> Result<(), Error> {
+        file.write_int(&[*self as c_int])
+    }
+}
+
+impl XdrDecode for bool {
+    fn decode(file: &mut XDRFile<access_mode::Read>) -> Result<Self, Error> {
+        match i32::decode(file)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            v => Err(ErrorKind::Custom(format!("invalid bool {}", v)).into()),
+        }
+    }
+}
+
+impl XdrEncode for u64 {
+    fn encode<MODE: XDRAccessMode + access_mode::Writable>(&self, file: &mut XDRFile<MODE>) -> Result<(), Error> {
+        // Most significant word first
+        file.write_uint(&[(*self >> 32) as c_uint, *self as c_uint])
+    }
+}
+
+impl XdrDecode for u64 {
+    fn decode(file: &mut XDRFile<access_mode::Read>) -> Result<Self, Error> {
+        let mut v = [0; 2];
+        file.read_uint(&mut v)?;
+        Ok((v[0] as u64) << 32 | v[1] as u64)
+    }
+}
+
+impl XdrEncode for i64 {
+    fn encode<MODE: XDRAccessMode + access_mode::Writable>(&self, file: &mut XDRFile<MODE>) -> Result<(), Error> {
+        (*self as u64).encode(file)
+    }
+}
+
+impl XdrDecode for i64 {
+    fn decode(file: &mut XDRFile<access_mode::Read>) -> Result<Self, Error> {
+        Ok(u64::decode(file)? as i64)
+    }
+}
+
+impl XdrEncode for str {
+    fn encode<MODE: XDRAccessMode + access_mode::Writable>(&self, file: &mut XDRFile<MODE>) -> Result<(), Error> {
+        file.write_string(self, c_uint::MAX as usize)
+    }
+}
+
+impl XdrEncode for String {
+    fn encode<MODE: XDRAccessMode + access_mode::Writable>(&self, file: &mut XDRFile<MODE>) -> Result<(), Error> {
+        self.as_str().encode(file)
+    }
+}
+
+impl XdrDecode for String {
+    fn decode(file: &mut XDRFile<access_mode::Read>) -> Result<Self, Error> {
+        file.read_string(c_uint::MAX as usize)
+    }
+}
+
+impl<T: XdrEncode> XdrEncode for [T] {
+    fn encode<MODE: XDRAccessMode + access_mode::Writable>(&self, file: &mut XDRFile<MODE>) -> Result<(), Error> {
+        let Ok(len) = c_uint::try_from(self.len()) else { return Err(ErrorKind::Overflow.into()) };
+        file.write_uint(&[len])?;
+        self.iter().try_for_each(|v| v.encode(file))
+    }
+}
+
+impl<T: XdrEncode> XdrEncode for Vec<T> {
+    fn encode<MODE: XDRAccessMode + access_mode::Writable>(&self, file: &mut XDRFile<MODE>) -> Result<(), Error> {
+        self.as_slice().encode(file)
+    }
+}
+
+impl<T: XdrDecode> XdrDecode for Vec<T> {
+    fn decode(file: &mut XDRFile<access_mode::Read>) -> Result<Self, Error> {
+        let len = u32::decode(file)? as usize;
+        decode_fixed(len, file)
+    }
+}
+
+impl<T: XdrEncode, const N: usize> XdrEncode for [T; N] {
+    fn encode<MODE: XDRAccessMode + access_mode::Writable>(&self, file: &mut XDRFile<MODE>) -> Result<(), Error> {
+        self.iter().try_for_each(|v| v.encode(file))
+    }
+}
+
+impl<T: XdrDecode, const N: usize> XdrDecode for [T; N] {
+    fn decode(file: &mut XDRFile<access_mode::Read>) -> Result<Self, Error> {
+        let values = decode_fixed(N, file)?;
+        Ok(values.try_into().unwrap_or_else(|_| unreachable!()))
+    }
+}
+
+impl<T: XdrEncode> XdrEncode for Option<T> {
+    fn encode<MODE: XDRAccessMode + access_mode::Writable>(&self, file: &mut XDRFile<MODE>) -> Result<(), Error> {
+        self.is_some().encode(file)?;
+        match self {
+            Some(v) => v.encode(file),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: XdrDecode> XdrDecode for Option<T> {
+    fn decode(file: &mut XDRFile<access_mode::Read>) -> Result<Self, Error> {
+        if bool::decode(file)? { T::decode(file).map(Some) } else { Ok(None) }
+    }
+}
+
+impl<T: XdrEncode + ?Sized> XdrEncode for &T {
+    fn encode<MODE: XDRAccessMode + access_mode::Writable>(&self, file: &mut XDRFile<MODE>) -> Result<(), Error> {
+        (**self).encode(file)
+    }
+}
+
+impl<T: XdrEncode + ?Sized> XdrEncode for Box<T> {
+    fn encode<MODE: XDRAccessMode + access_mode::Writable>(&self, file: &mut XDRFile<MODE>) -> Result<(), Error> {
+        (**self).encode(file)
+    }
+}
+
+impl<T: XdrDecode> XdrDecode for Box<T> {
+    fn decode(file: &mut XDRFile<access_mode::Read>) -> Result<Self, Error> {
+        T::decode(file).map(Box::new)
+    }
+}
+
+/// Error for a value with the wrong number of elements
+fn length_mismatch(expected: usize, found: usize) -> Error {
+    ErrorKind::Custom(format!("expected {} elements, found {}", expected, found)).into()
+}
+
+/// Write exactly `len` values without their length, as for `#[xdr(fixed = len)]`
+pub fn encode_fixed<T: XdrEncode, MODE: XDRAccessMode + access_mode::Writable>(
+    values: &[T],
+    len: usize,
+    file: &mut XDRFile<MODE>,
+) -> Result<(), Error> {
+    if values.len() != len {
+        return Err(length_mismatch(len, values.len()))
+    }
+    values.iter().try_for_each(|v| v.encode(file))
+}
+
+/// Read `len` values which were written without their length, as for `#[xdr(fixed = len)]`
+pub fn decode_fixed<T: XdrDecode>(len: usize, file: &mut XDRFile<access_mode::Read>) -> Result<Vec<T>, Error> {
+    let mut values = Vec::with_capacity(len.min(MAX_PREALLOC));
+    for _ in 0..len {
+        values.push(T::decode(file)?);
+    }
+    Ok(values)
+}
+
+/// Write `n` bytes of zero padding, rounded up to a multiple of 4 bytes
+pub fn encode_padding<MODE: XDRAccessMode + access_mode::Writable>(n: usize, file: &mut XDRFile<MODE>) -> Result<(), Error> {
+    file.write_opaque(&vec![0; n])
+}
+
+/// Skip `n` bytes of padding, rounded up to a multiple of 4 bytes
+pub fn decode_padding(n: usize, file: &mut XDRFile<access_mode::Read>) -> Result<(), Error> {
+    file.read_opaque(&mut vec![0; n])
+}
+
+/// Error for an enum discriminant which doesn't match any variant
+pub fn unknown_variant(name: &str, tag: i32) -> Error {
+    ErrorKind::Custom(format!("invalid discriminant {} for {}", tag, name)).into()
+}
+
+/// Bytes stored as opaque data, as for `#[xdr(opaque)]`. With a `fixed` length, there must be
+/// exactly that many bytes, which are stored without their length.
+pub trait Opaque: Sized {
+    fn encode_opaque<MODE: XDRAccessMode + access_mode::Writable>(
+        &self,
+        fixed: Option<usize>,
+        file: &mut XDRFile<MODE>,
+    ) -> Result<(), Error>;
+    fn decode_opaque(fixed: Option<usize>, file: &mut XDRFile<access_mode::Read>) -> Result<Self, Error>;
+}
+
+impl Opaque for Vec<u8> {
+    fn encode_opaque<MODE: XDRAccessMode + access_mode::Writable>(
+        &self,
+        fixed: Option<usize>,
+        file: &mut XDRFile<MODE>,
+    ) -> Result<(), Error> {
+        match fixed {
+            Some(len) if len != self.len() => return Err(length_mismatch(len, self.len())),
+            Some(_) => (),
+            None => {
+                let Ok(len) = c_uint::try_from(self.len()) else { return Err(ErrorKind::Overflow.into()) };
+                file.write_uint(&[len])?;
+            }
+        }
+        file.write_opaque(self)
+    }
+
+    fn decode_opaque(fixed: Option<usize>, file: &mut XDRFile<access_mode::Read>) -> Result<Self, Error> {
+        let len = match fixed {
+            Some(len) => len,
+            None => u32::decode(file)? as usize,
+        };
+        let offset = file.offset();
+        let mut bytes = Vec::new();
+        if !file.handle.read_opaque_vec(&mut bytes, len) {
+            return Err(file.error_at(XDRStatus::exdrSTRING, offset))
+        }
+        Ok(bytes)
+    }
+}
+
+impl<const N: usize> Opaque for [u8; N] {
+    fn encode_opaque<MODE: XDRAccessMode + access_mode::Writable>(
+        &self,
+        fixed: Option<usize>,
+        file: &mut XDRFile<MODE>,
+    ) -> Result<(), Error> {
+        if fixed.is_some_and(|len| len != N) {
+            return Err(length_mismatch(fixed.unwrap_or(N), N))
+        }
+        file.write_opaque(self)
+    }
+
+    fn decode_opaque(fixed: Option<usize>, file: &mut XDRFile<access_mode::Read>) -> Result<Self, Error> {
+        if fixed.is_some_and(|len| len != N) {
+            return Err(length_mismatch(fixed.unwrap_or(N), N))
+        }
+        let mut bytes = [0; N];
+        file.read_opaque(&mut bytes)?;
+        Ok(bytes)
+    }
+}
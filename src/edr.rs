@@ -0,0 +1,373 @@
+//! Reader for GROMACS .edr energy files.
+//!
+//! An edr file starts with the names and units of the energy terms, followed by frames which
+//! each hold a value for every term, along with any blocks of additional data such as
+//! free energy differences. Real numbers are stored in the precision GROMACS was built with,
+//! which is detected from the first frame.
+
+use std::{
+    ffi::{c_int, c_float, c_double, c_uint},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    Error, ErrorKind, MAX_PREALLOC, XDRFile, access_mode,
+    codec::decode_fixed,
+    xdr::XDRStatus,
+};
+
+/// Magic number at the start of the energy term names
+const NAMES_MAGIC: c_int = -55555;
+/// Magic number at the start of each frame, following a real number
+const FRAME_MAGIC: c_int = -7777777;
+/// Latest version of the edr format
+const VERSION: c_int = 5;
+
+/// Name and unit of an energy term
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnergyTerm {
+    pub name: String,
+    pub unit: String,
+}
+
+/// Value of an energy term in a frame
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Energy {
+    /// Instantaneous value
+    pub e: c_double,
+    /// Average over the `nsum` steps since the previous frame, if `nsum` > 0
+    pub eav: c_double,
+    /// Sum over the `nsum` steps since the previous frame, if `nsum` > 0
+    pub esum: c_double,
+}
+
+/// Data of a sub-block of an [`EnergyBlock`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnergySubBlock {
+    Int(Vec<c_int>),
+    Float(Vec<c_float>),
+    Double(Vec<c_double>),
+    Int64(Vec<i64>),
+    Char(Vec<u8>),
+    String(Vec<String>),
+}
+
+/// Block of additional data in an energy frame. `id` identifies the kind of data, such as
+/// orientation restraints or free energy differences, following GROMACS.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnergyBlock {
+    pub id: c_int,
+    pub sub: Vec<EnergySubBlock>,
+}
+
+/// Frame of an edr file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnergyFrame {
+    pub time: c_double,
+    pub step: i64,
+    /// Number of steps included in the averages and sums of `energies`, or 0 if only
+    /// instantaneous values are stored
+    pub nsum: c_int,
+    /// Number of steps since the previous frame
+    pub nsteps: i64,
+    /// Time step
+    pub dt: c_double,
+    /// Value of each energy term, in the order of [`EdrReader::terms`].
+    /// Empty if the frame only holds block data.
+    pub energies: Vec<Energy>,
+    pub blocks: Vec<EnergyBlock>,
+}
+
+/// Reader for the frames of an edr file, which iterates over the frames following the energy
+/// term names.
+///
+/// Iteration stops at the end of the file. Any other error is returned once, after which the
+/// iterator is exhausted. Files in the original format without a version number, written by
+/// very old versions of GROMACS, aren't supported.
+pub struct EdrReader {
+    file: XDRFile<access_mode::Read>,
+    terms: Vec<EnergyTerm>,
+    /// Whether reals are stored in double precision, once known from the first frame
+    double: Option<bool>,
+    done: bool,
+}
+
+impl EdrReader {
+    /// Open the edr file at `path` and read the energy term names
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::new(XDRFile::open_path(path)?)
+    }
+
+    /// Read the energy term names from the start of an edr file
+    pub fn new(mut file: XDRFile<access_mode::Read>) -> Result<Self, Error> {
+        let offset = file.offset();
+        let header_error = || Error::from(XDRStatus::exdrHEADER).at(None, offset);
+        if file.decode::<c_int>()? != NAMES_MAGIC {
+            return Err(Error::from(XDRStatus::exdrMAGIC).at(None, offset))
+        }
+        let version: c_int = file.decode()?;
+        if !(1..=VERSION).contains(&version) {
+            return Err(header_error())
+        }
+        let Ok(nre) = usize::try_from(file.decode::<c_int>()?) else { return Err(header_error()) };
+        let mut terms = Vec::with_capacity(nre.min(MAX_PREALLOC));
+        for _ in 0..nre {
+            let name = file.read_string(c_uint::MAX as usize)?;
+            let unit = if version >= 2 { file.read_string(c_uint::MAX as usize)? } else { "kJ/mol".to_string() };
+            terms.push(EnergyTerm { name, unit });
+        }
+        Ok(Self { file, terms, double: None, done: false })
+    }
+
+    /// Names and units of the energy terms
+    pub fn terms(&self) -> &[EnergyTerm] {
+        &self.terms
+    }
+
+    /// Index of the energy term called `name`
+    pub fn term_index(&self, name: &str) -> Option<usize> {
+        self.terms.iter().position(|t| t.name == name)
+    }
+
+    /// Whether reals are stored in double precision. Only known once a frame has been read.
+    pub fn is_double(&self) -> Option<bool> {
+        self.double
+    }
+
+    /// Return the underlying file
+    pub fn into_inner(self) -> XDRFile<access_mode::Read> {
+        self.file
+    }
+
+    /// Read the next frame. Returns an error for which [`Error::is_eof`] is true at the end
+    /// of the file.
+    pub fn read_frame(&mut self) -> Result<EnergyFrame, Error> {
+        let offset = self.file.offset();
+        match self.read_frame_data() {
+            Ok(frame) => {
+                self.file.frame = self.file.frame.map(|n| n + 1);
+                Ok(frame)
+            }
+            Err(e) => Err(self.file.error_at(e.into_kind(), offset)),
+        }
+    }
+
+    /// Read the remaining frames, collecting the values of the energy terms called `names`.
+    /// Frames which only hold block data are skipped.
+    pub fn series(&mut self, names: &[&str]) -> Result<EnergySeries, Error> {
+        let mut indices = Vec::with_capacity(names.len());
+        for name in names {
+            let Some(index) = self.term_index(name) else {
+                return Err(ErrorKind::Custom(format!("no energy term called {}", name)).into())
+            };
+            indices.push(index);
+        }
+        let mut series = EnergySeries {
+            terms: indices.iter().map(|&i| self.terms[i].clone()).collect(),
+            time: Vec::new(),
+            step: Vec::new(),
+            values: vec![Vec::new(); indices.len()],
+        };
+        for frame in self {
+            let frame = frame?;
+            if frame.energies.is_empty() {
+                continue
+            }
+            series.time.push(frame.time);
+            series.step.push(frame.step);
+            for (values, &i) in series.values.iter_mut().zip(&indices) {
+                values.push(frame.energies[i].e);
+            }
+        }
+        Ok(series)
+    }
+
+    fn read_real(&mut self, double: bool) -> Result<c_double, Error> {
+        if double { self.file.decode() } else { Ok(self.file.decode::<c_float>()?.into()) }
+    }
+
+    fn read_count(&mut self) -> Result<usize, Error> {
+        usize::try_from(self.file.decode::<c_int>()?).map_err(|_| XDRStatus::exdrHEADER.into())
+    }
+
+    /// Read a frame, working out the precision of reals from the position of the magic
+    /// number in the first frame
+    fn read_frame_data(&mut self) -> Result<EnergyFrame, Error> {
+        let mut word = [0];
+        if self.file.handle.read_int(&mut word) != 1 {
+            return Err(XDRStatus::exdrENDOFFILE.into())
+        }
+        // The frame starts with a real, followed by the magic number
+        let second: c_int = self.file.decode()?;
+        let (double, magic) = match self.double {
+            Some(false) => (false, second),
+            Some(true) => (true, self.file.decode()?),
+            None if second == FRAME_MAGIC => (false, second),
+            None => (true, self.file.decode()?),
+        };
+        self.double = Some(double);
+        if magic != FRAME_MAGIC {
+            return Err(XDRStatus::exdrMAGIC.into())
+        }
+        let version: c_int = self.file.decode()?;
+        if !(2..=VERSION).contains(&version) {
+            return Err(XDRStatus::exdrHEADER.into())
+        }
+        let mut frame = EnergyFrame {
+            time: self.file.decode()?,
+            step: self.file.decode()?,
+            nsum: self.file.decode()?,
+            ..Default::default()
+        };
+        frame.nsteps = if version >= 3 { self.file.decode()? } else { frame.nsum.max(1).into() };
+        frame.dt = if version >= 5 { self.file.decode()? } else { 0. };
+        let nre = self.read_count()?;
+        if nre != 0 && nre != self.terms.len() {
+            return Err(XDRStatus::exdrHEADER.into())
+        }
+        // Distance restraints had their own block count in old versions
+        let ndisre: c_int = self.file.decode()?;
+        let ndisre = if version < 4 { usize::try_from(ndisre).map_err(|_| XDRStatus::exdrHEADER)? } else { 0 };
+        let nblock = self.read_count()?;
+
+        // Types and sizes of the sub-blocks of each block
+        let real_type = if double { 2 } else { 1 };
+        let mut layout = Vec::with_capacity(nblock.min(MAX_PREALLOC) + 1);
+        if ndisre > 0 {
+            // Distance restraint block id, with instantaneous and time averaged data
+            layout.push((3, vec![(real_type, ndisre), (0, ndisre)]));
+        }
+        for b in 0..nblock {
+            if version < 4 {
+                layout.push((b as c_int, vec![(real_type, self.read_count()?)]));
+            } else {
+                let id = self.file.decode()?;
+                let nsub = self.read_count()?;
+                let mut sub = Vec::with_capacity(nsub.min(MAX_PREALLOC));
+                for _ in 0..nsub {
+                    sub.push((self.file.decode::<c_int>()?, self.read_count()?));
+                }
+                layout.push((id, sub));
+            }
+        }
+        // Size of the energies, followed by two unused ints
+        let mut unused = [0; 3];
+        self.file.read_int(&mut unused)?;
+
+        frame.energies.reserve(nre);
+        for _ in 0..nre {
+            let e = self.read_real(double)?;
+            let (eav, esum) = if frame.nsum > 0 {
+                (self.read_real(double)?, self.read_real(double)?)
+            } else {
+                (0., 0.)
+            };
+            frame.energies.push(Energy { e, eav, esum });
+        }
+
+        for (id, sub) in layout {
+            let mut block = EnergyBlock { id, sub: Vec::with_capacity(sub.len()) };
+            for (kind, nr) in sub {
+                block.sub.push(self.read_sub_block(kind, nr)?);
+            }
+            frame.blocks.push(block);
+        }
+        Ok(frame)
+    }
+
+    /// Read the data of a sub-block, with the type numbering of GROMACS
+    fn read_sub_block(&mut self, kind: c_int, nr: usize) -> Result<EnergySubBlock, Error> {
+        let file = &mut self.file;
+        Ok(match kind {
+            0 => EnergySubBlock::Int(decode_fixed(nr, file)?),
+            1 => EnergySubBlock::Float(decode_fixed(nr, file)?),
+            2 => EnergySubBlock::Double(decode_fixed(nr, file)?),
+            3 => EnergySubBlock::Int64(decode_fixed(nr, file)?),
+            4 => EnergySubBlock::Char(decode_fixed(nr, file)?),
+            5 => {
+                let mut strings = Vec::with_capacity(nr.min(MAX_PREALLOC));
+                for _ in 0..nr {
+                    // Length including the terminating null, followed by the string itself
+                    file.decode::<c_int>()?;
+                    strings.push(file.read_string(c_uint::MAX as usize)?);
+                }
+                EnergySubBlock::String(strings)
+            }
+            _ => return Err(XDRStatus::exdrHEADER.into()),
+        })
+    }
+}
+
+impl Iterator for EdrReader {
+    type Item = Result<EnergyFrame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None
+        }
+        match self.read_frame() {
+            Ok(frame) => Some(Ok(frame)),
+            Err(e) => {
+                self.done = true;
+                if e.is_eof() { None } else { Some(Err(e)) }
+            }
+        }
+    }
+}
+
+impl std::iter::FusedIterator for EdrReader {}
+
+/// Time series of selected energy terms. See [`EdrReader::series`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnergySeries {
+    pub terms: Vec<EnergyTerm>,
+    pub time: Vec<c_double>,
+    pub step: Vec<i64>,
+    /// Values of each term, in the order of `terms`, with one value per entry of `time`
+    pub values: Vec<Vec<c_double>>,
+}
+
+impl EnergySeries {
+    /// Values of the term called `name`
+    pub fn get(&self, name: &str) -> Option<&[c_double]> {
+        let index = self.terms.iter().position(|t| t.name == name)?;
+        Some(&self.values[index])
+    }
+
+    /// Write the time series to an xvg file at `path`, in the layout used by `gmx energy`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.to_writer(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the time series in xvg format, with a column for the time followed by a column
+    /// for each term
+    pub fn to_writer<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let mut units: Vec<&str> = Vec::new();
+        for term in &self.terms {
+            if !units.contains(&term.unit.as_str()) {
+                units.push(&term.unit);
+            }
+        }
+        let units: Vec<String> = units.iter().map(|u| format!("({})", u)).collect();
+        writeln!(writer, "@    title \"GROMACS Energies\"")?;
+        writeln!(writer, "@    xaxis  label \"Time (ps)\"")?;
+        writeln!(writer, "@    yaxis  label \"{}\"", units.join(", "))?;
+        writeln!(writer, "@TYPE xy")?;
+        for (i, term) in self.terms.iter().enumerate() {
+            writeln!(writer, "@ s{} legend \"{}\"", i, term.name)?;
+        }
+        for (i, time) in self.time.iter().enumerate() {
+            write!(writer, "{:12.6}", time)?;
+            for values in &self.values {
+                write!(writer, "  {:12.6}", values[i])?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
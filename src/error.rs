@@ -0,0 +1,179 @@
+use std::{fmt, io};
+
+use crate::xdr::XDRStatus;
+
+/// Error type for the safe interface to xdr files.
+///
+/// As well as the kind of error, this records the index of the frame being read or written when
+/// the error occurred, and the byte offset of the start of that frame, where they are known.
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    frame: Option<usize>,
+    offset: Option<u64>,
+}
+
+/// The different kinds of [`Error`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Error status from reading or writing xdr data
+    Status(XDRStatus),
+    /// Error from the underlying file
+    Io(io::Error),
+    /// Number of atoms in a frame doesn't match the number expected
+    NatomsMismatch { expected: usize, found: usize },
+    /// An atom index is out of range for the number of atoms in a frame
+    AtomIndex { index: usize, natoms: usize },
+    /// Text file couldn't be parsed. `line` counts from 1.
+    Parse { line: usize, message: String },
+    /// A value is too large to be converted to the type stored in the file, such as an atom
+    /// count that doesn't fit in a `c_int`
+    Overflow,
+    /// Error message from a serde `Serialize` or `Deserialize` implementation, or a type that
+    /// can't be represented in xdr
+    Custom(String),
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind) -> Self {
+        Self { kind, frame: None, offset: None }
+    }
+
+    /// Record the frame index and byte offset where the error occurred
+    pub fn at(mut self, frame: Option<usize>, offset: Option<u64>) -> Self {
+        self.frame = frame;
+        self.offset = offset;
+        self
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    pub fn into_kind(self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Index of the frame being read or written when the error occurred, if known
+    pub fn frame(&self) -> Option<usize> {
+        self.frame
+    }
+
+    /// Byte offset of the start of the frame being read or written when the error occurred,
+    /// if known
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    /// The xdr status, if this error came from reading or writing xdr data
+    pub fn status(&self) -> Option<XDRStatus> {
+        match self.kind {
+            ErrorKind::Status(status) => Some(status),
+            _ => None,
+        }
+    }
+
+    /// Whether this error indicates the end of the file was reached before a frame started
+    pub fn is_eof(&self) -> bool {
+        self.status() == Some(XDRStatus::exdrENDOFFILE)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(frame) = self.frame {
+            write!(f, " in frame {}", frame)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at byte offset {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Status(status) => write!(f, "{}", status),
+            // The underlying error is available from `source()`
+            Self::Io(_) => write!(f, "I/O error"),
+            Self::NatomsMismatch { expected, found } => write!(f, "expected {} atoms, found {}", expected, found),
+            Self::AtomIndex { index, natoms } => write!(f, "atom index {} out of range for {} atoms", index, natoms),
+            Self::Parse { line, message } => write!(f, "parse error on line {}: {}", line, message),
+            Self::Overflow => write!(f, "value too large to convert"),
+            Self::Custom(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl From<XDRStatus> for ErrorKind {
+    fn from(status: XDRStatus) -> Self {
+        Self::Status(status)
+    }
+}
+
+impl From<io::Error> for ErrorKind {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<XDRStatus> for Error {
+    fn from(status: XDRStatus) -> Self {
+        Self::new(status.into())
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::new(e.into())
+    }
+}
+
+impl PartialEq<XDRStatus> for Error {
+    fn eq(&self, other: &XDRStatus) -> bool {
+        self.status() == Some(*other)
+    }
+}
+
+impl fmt::Display for XDRStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Messages from exdr_message in libxdrfile
+        let msg = match self {
+            Self::exdrOK => "OK",
+            Self::exdrHEADER => "Header",
+            Self::exdrSTRING => "String",
+            Self::exdrDOUBLE => "Double",
+            Self::exdrINT => "Integer",
+            Self::exdrFLOAT => "Float",
+            Self::exdrUINT => "Unsigned integer",
+            Self::exdr3DX => "Compressed 3D coordinate",
+            Self::exdrCLOSE => "Closing file",
+            Self::exdrMAGIC => "Magic number",
+            Self::exdrNOMEM => "Not enough memory",
+            Self::exdrENDOFFILE => "End of file",
+            Self::exdrFILENOTFOUND => "File not found",
+            Self::exdrNR => "Unknown error",
+        };
+        write!(f, "xdr error: {}", msg)
+    }
+}
+
+impl std::error::Error for XDRStatus {}
@@ -0,0 +1,122 @@
+use std::{
+    io::{self, SeekFrom},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    Error, XDRFile, XTCFrame, access_mode,
+    frames::read_header,
+    xdr::*,
+};
+
+impl XDRFile<access_mode::Read> {
+    /// Iterate over the remaining frames of an xtc file which is still being written, such as
+    /// by a running simulation.
+    ///
+    /// When the end of the file is reached, including part way through a frame which hasn't
+    /// been written in full yet, the iterator waits for the file to grow, checking its size every
+    /// `poll_interval`, then carries on from the start of that frame. Iteration stops if the file
+    /// doesn't grow for `idle_timeout`, or never if it is `None`.
+    ///
+    /// Only data which is complete according to the size of the file is treated as damaged, so
+    /// other errors are still returned once, after which the iterator is exhausted. The file
+    /// must be seekable and have metadata, so this can't be used with streams.
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use xdrfile::prelude::*;
+    /// # fn main() -> Result<(), Error> {
+    /// let mut xtc = XDRFile::<access_mode::Read>::open(c"traj.xtc")?;
+    /// for frame in xtc.follow(Duration::from_secs(1), Some(Duration::from_secs(600))) {
+    ///     let frame = frame?;
+    ///     println!("{} {}", frame.step, frame.time);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn follow(&mut self, poll_interval: Duration, idle_timeout: Option<Duration>) -> XTCFollow<'_> {
+        XTCFollow {
+            file: self,
+            poll_interval,
+            idle_timeout,
+            done: false,
+        }
+    }
+
+    /// Read the frame at the current position if it has been written in full.
+    /// Returns `None`, leaving the position unchanged, if the file ends before the end of the frame.
+    fn read_xtc_complete(&mut self) -> Result<Option<XTCFrame>, Error> {
+        let offset = self.handle.position();
+        let file_size = self.handle.metadata()?.len();
+        if file_size < offset {
+            let e = io::Error::new(io::ErrorKind::InvalidData, "file was truncated while being followed");
+            return Err(Error::from(e).at(self.frame, Some(offset)))
+        }
+        let complete = match read_header(&mut self.handle, Some(file_size)) {
+            Ok(_) => true,
+            Err(_) if self.handle.position() >= file_size => false,
+            Err(XDRStatus::exdrENDOFFILE) => false,
+            Err(e) => return Err(self.error_at(e, Some(offset))),
+        };
+        // Seeking from the start drops any buffered data, so data written since is read
+        match self.handle.seek(SeekFrom::Start(offset)) {
+            XDRStatus::exdrOK => (),
+            e => return Err(self.error_at(e, Some(offset))),
+        }
+        if !complete {
+            return Ok(None)
+        }
+        let mut frame = XTCFrame::empty();
+        self.read_xtc_next(&mut frame)?;
+        Ok(Some(frame))
+    }
+}
+
+/// Iterator over the frames of an xtc file which is still being written. See
+/// [`XDRFile::follow`].
+pub struct XTCFollow<'a> {
+    file: &'a mut XDRFile<access_mode::Read>,
+    poll_interval: Duration,
+    idle_timeout: Option<Duration>,
+    done: bool,
+}
+
+impl Iterator for XTCFollow<'_> {
+    type Item = Result<XTCFrame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None
+        }
+        let mut last_size = None;
+        let mut last_change = Instant::now();
+        loop {
+            match self.file.read_xtc_complete() {
+                Ok(Some(frame)) => return Some(Ok(frame)),
+                Ok(None) => (),
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e))
+                }
+            }
+            let size = match self.file.handle.metadata() {
+                Ok(metadata) => metadata.len(),
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.into()))
+                }
+            };
+            if last_size != Some(size) {
+                last_size = Some(size);
+                last_change = Instant::now();
+            } else if self.idle_timeout.is_some_and(|timeout| last_change.elapsed() >= timeout) {
+                self.done = true;
+                return None
+            }
+            thread::sleep(self.poll_interval);
+        }
+    }
+}
+
+impl std::iter::FusedIterator for XTCFollow<'_> {}
@@ -0,0 +1,249 @@
+use std::ffi::{c_int, c_float};
+
+use crate::{
+    Error, XDRFile, XTCFrame, access_mode,
+    xdr::*,
+    xtc::*,
+};
+
+impl XDRFile<access_mode::Read> {
+    /// Iterate over the remaining frames of an xtc file, starting at the current position.
+    /// Each frame is sized according to the number of atoms in its header.
+    ///
+    /// Iteration stops at the end of the file. Any other error is returned once, after which
+    /// the iterator is exhausted.
+    pub fn frames(&mut self) -> XTCFrames<'_> {
+        XTCFrames { file: self, done: false }
+    }
+
+    /// Lending iterator over the remaining frames of an xtc file, which reuses a single frame
+    /// buffer in the same way as [`XDRFile::read_xtc_reuse`].
+    ///
+    /// ```no_run
+    /// # use xdrfile::prelude::*;
+    /// # fn main() -> Result<(), Error> {
+    /// let mut xtc = XDRFile::<access_mode::Read>::open(c"traj.xtc")?;
+    /// let mut reader = xtc.reader();
+    /// while let Some(frame) = reader.next() {
+    ///     let frame = frame?;
+    ///     println!("{} {}", frame.step, frame.x.len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reader(&mut self) -> XTCReader<'_> {
+        XTCReader {
+            file: self,
+            frame: XTCFrame::empty(),
+            done: false,
+        }
+    }
+
+    /// Read the header of the next xtc frame, then skip its coordinates without decompressing
+    /// them.
+    pub fn read_xtc_header(&mut self) -> Result<FrameHeader, Error> {
+        let file_size = self.handle.stream_len().ok();
+        self.read_xtc_header_within(file_size)
+    }
+
+    /// Read the header of the next xtc frame, checking the frame ends within `file_size`
+    fn read_xtc_header_within(&mut self, file_size: Option<u64>) -> Result<FrameHeader, Error> {
+        let offset = self.offset();
+        match read_header(&mut self.handle, file_size) {
+            Ok(header) => {
+                self.frame = self.frame.map(|n| n + 1);
+                Ok(header)
+            }
+            Err(e) => Err(self.error_at(e, offset)),
+        }
+    }
+
+    /// Iterate over the headers of the remaining frames of an xtc file, starting at the
+    /// current position. The coordinates are skipped without being decompressed.
+    ///
+    /// Iteration stops at the end of the file, as it was when the iterator was created. Any other
+    /// error is returned once, after which the iterator is exhausted.
+    pub fn headers(&mut self) -> XTCHeaders<'_> {
+        let file_size = self.handle.stream_len().ok();
+        XTCHeaders { file: self, file_size, done: false }
+    }
+
+    /// Read the headers of every frame in an xtc file, without decompressing any coordinates.
+    ///
+    /// A truncated frame at the end of the file is not included.
+    /// The current position in the file is preserved.
+    pub fn scan_headers(&mut self) -> Result<Vec<FrameHeader>, Error> {
+        let file_size = self.handle.stream_len()?;
+        self.peek_start(|xd| {
+            let mut headers = Vec::new();
+            loop {
+                let offset = xd.position();
+                match read_header(xd, Some(file_size)) {
+                    Ok(header) => headers.push(header),
+                    Err(XDRStatus::exdrENDOFFILE) => break,
+                    // Data stopped part way through the frame
+                    Err(_) if xd.position() >= file_size => break,
+                    Err(e) => return Err(Error::from(e).at(Some(headers.len()), Some(offset))),
+                }
+            }
+            Ok(headers)
+        })
+    }
+
+    /// Read the next xtc frame into `frame`, resizing `frame.x` to the number of atoms in the
+    /// frame header.
+    pub(crate) fn read_xtc_next(&mut self, frame: &mut XTCFrame) -> Result<(), Error> {
+        let offset = self.offset();
+        let mut natoms: c_int = 0;
+        match xtc_header(&mut self.handle, &mut natoms, &mut frame.step, &mut frame.time, true) {
+            XDRStatus::exdrOK => (),
+            e => return Err(self.error_at(e, offset)),
+        }
+        let Ok(n) = usize::try_from(natoms) else { return Err(self.error_at(XDRStatus::exdrHEADER, offset)) };
+        if self.handle.read_float(frame.sim_box.0.as_flattened_mut()) != DIM * DIM {
+            return Err(self.error_at(XDRStatus::exdrFLOAT, offset))
+        }
+
+        // The number of atoms hasn't been checked against anything else, so `frame.x` grows as
+        // atoms are decoded, rather than trusting it for the allocation
+        frame.x.clear();
+        let x = &mut frame.x;
+        let mut ncoord = natoms;
+        let read = self.handle.decompress_coord_each(&mut ncoord, &mut frame.prec, n, |_, c| x.push(rvec(c)));
+        let result = if read == natoms { XDRStatus::exdrOK } else { XDRStatus::exdr3DX };
+        self.frame_result(result, offset)
+    }
+}
+
+/// Header information of an xtc frame, which can be read without decompressing the
+/// coordinates. See [`XDRFile::read_xtc_header`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameHeader {
+    pub natoms: usize,
+    pub step: c_int,
+    pub time: c_float,
+    pub sim_box: matrix,
+    /// Precision of the compressed coordinates, or 0 for frames with 9 atoms or fewer, which
+    /// are stored uncompressed
+    pub prec: c_float,
+    /// Number of bytes of coordinate data, excluding padding
+    pub nbytes: usize,
+    /// Byte offset of the start of the frame
+    pub offset: u64,
+}
+
+/// Read the header of an xtc frame and skip its coordinates. Returns `exdr3DX` if the frame
+/// runs past `file_size`.
+pub(crate) fn read_header(xd: &mut XDRFILE, file_size: Option<u64>) -> Result<FrameHeader, XDRStatus> {
+    let offset = xd.position();
+    let mut natoms: c_int = 0;
+    let mut step: c_int = 0;
+    let mut time: c_float = 0.;
+    match xtc_header(xd, &mut natoms, &mut step, &mut time, true) {
+        XDRStatus::exdrOK => (),
+        e => return Err(e),
+    }
+    let mut sim_box = matrix::new();
+    let mut prec: c_float = 0.;
+    let mut nbytes = 0;
+    match xtc_coord_info(xd, natoms, &mut sim_box, &mut prec, &mut nbytes) {
+        XDRStatus::exdrOK => (),
+        e => return Err(e),
+    }
+    if file_size.is_some_and(|size| xd.position() > size) {
+        return Err(XDRStatus::exdr3DX)
+    }
+    Ok(FrameHeader {
+        natoms: natoms as usize,
+        step,
+        time,
+        sim_box,
+        prec,
+        nbytes: usize::try_from(nbytes).map_err(|_| XDRStatus::exdr3DX)?,
+        offset,
+    })
+}
+
+/// Iterator over the frame headers of an xtc file. See [`XDRFile::headers`].
+pub struct XTCHeaders<'a> {
+    file: &'a mut XDRFile<access_mode::Read>,
+    /// Size of the file when iteration started, if it is known
+    file_size: Option<u64>,
+    done: bool,
+}
+
+impl Iterator for XTCHeaders<'_> {
+    type Item = Result<FrameHeader, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Frames written after iteration started aren't included
+        if self.done || self.file_size.is_some_and(|size| self.file.handle.position() >= size) {
+            return None
+        }
+        match self.file.read_xtc_header_within(self.file_size) {
+            Ok(header) => Some(Ok(header)),
+            Err(e) => {
+                self.done = true;
+                if e.is_eof() { None } else { Some(Err(e)) }
+            }
+        }
+    }
+}
+
+impl std::iter::FusedIterator for XTCHeaders<'_> {}
+
+/// Iterator over the frames of an xtc file. See [`XDRFile::frames`].
+pub struct XTCFrames<'a> {
+    file: &'a mut XDRFile<access_mode::Read>,
+    done: bool,
+}
+
+impl Iterator for XTCFrames<'_> {
+    type Item = Result<XTCFrame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None
+        }
+        let mut frame = XTCFrame::empty();
+        match self.file.read_xtc_next(&mut frame) {
+            Ok(()) => Some(Ok(frame)),
+            Err(e) => {
+                self.done = true;
+                if e.is_eof() { None } else { Some(Err(e)) }
+            }
+        }
+    }
+}
+
+impl std::iter::FusedIterator for XTCFrames<'_> {}
+
+/// Lending iterator over the frames of an xtc file. See [`XDRFile::reader`].
+pub struct XTCReader<'a> {
+    file: &'a mut XDRFile<access_mode::Read>,
+    frame: XTCFrame,
+    done: bool,
+}
+
+impl XTCReader<'_> {
+    /// Read the next frame into the internal buffer and return a reference to it.
+    /// Returns `None` at the end of the file, or after an error has been returned.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<&XTCFrame, Error>> {
+        if self.done {
+            return None
+        }
+        match self.file.read_xtc_next(&mut self.frame) {
+            Ok(()) => Some(Ok(&self.frame)),
+            Err(e) => {
+                self.done = true;
+                if e.is_eof() { None } else { Some(Err(e)) }
+            }
+        }
+    }
+
+    /// Consume the reader, returning the frame buffer
+    pub fn into_frame(self) -> XTCFrame {
+        self.frame
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    ffi::{c_int, c_float},
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    Error, ErrorKind, MAX_PREALLOC, XTCFrame,
+    xdr::*,
+};
+
+/// Identity of an atom in a `.gro` file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroAtom {
+    pub res_number: i32,
+    pub res_name: String,
+    pub name: String,
+    pub number: i32,
+}
+
+/// Contents of a GROMACS structure (`.gro`) file
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroFile {
+    pub title: String,
+    pub atoms: Vec<GroAtom>,
+    /// Atom positions in nm
+    pub x: Vec<rvec>,
+    /// Atom velocities in nm/ps, if present
+    pub v: Option<Vec<rvec>>,
+    pub sim_box: matrix,
+    /// Number of decimal places of the positions, which is 3 in files written by GROMACS unless
+    /// extra precision is asked for. Velocities have one more, in fields of the same width.
+    pub precision: usize,
+}
+
+impl GroFile {
+    /// Read a `.gro` file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_reader(File::open(path)?)
+    }
+
+    /// Parse the first frame of a `.gro` file from a stream.
+    ///
+    /// As in GROMACS, the precision of the coordinates is worked out from the distance between
+    /// the decimal points of the first atom, so files written with extra precision can be read.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        let mut lines = BufReader::new(reader).lines();
+        let mut line_number = 0;
+        let mut next_line = |what: &str| -> Result<(usize, String), Error> {
+            line_number += 1;
+            match lines.next() {
+                Some(line) => Ok((line_number, line?)),
+                None => Err(parse_error(line_number, format!("missing {}", what))),
+            }
+        };
+
+        let (_, title) = next_line("title")?;
+        let (n, line) = next_line("number of atoms")?;
+        let Ok(natoms) = line.trim().parse::<usize>() else {
+            return Err(parse_error(n, format!("invalid number of atoms '{}'", line.trim())))
+        };
+
+        let mut atoms = Vec::with_capacity(natoms.min(MAX_PREALLOC));
+        let mut x = Vec::with_capacity(natoms.min(MAX_PREALLOC));
+        let mut v: Option<Vec<rvec>> = None;
+        let mut ddist = 0;
+        for i in 0..natoms {
+            let (n, line) = next_line("atom")?;
+            let field = |start: usize, end: usize| line.get(start..end.min(line.len())).unwrap_or("");
+            let int = |start: usize, what: &str| field(start, start + 5).trim().parse::<i32>()
+                .map_err(|_| parse_error(n, format!("invalid {} '{}'", what, field(start, start + 5))));
+            atoms.push(GroAtom {
+                res_number: int(0, "residue number")?,
+                res_name: field(5, 10).trim().to_string(),
+                name: field(10, 15).trim().to_string(),
+                number: int(15, "atom number")?,
+            });
+
+            if i == 0 {
+                ddist = decimal_spacing(&line).ok_or_else(|| parse_error(n, "can't find coordinates".into()))?;
+                // Velocities are only read if the first atom has them
+                if !field(20 + 3 * ddist, line.len()).trim().is_empty() {
+                    v = Some(Vec::with_capacity(natoms.min(MAX_PREALLOC)));
+                }
+            }
+            let vector = |start: usize, what: &str| -> Result<rvec, Error> {
+                let mut r = rvec::new();
+                for (d, c) in r.0.iter_mut().enumerate() {
+                    let s = field(start + d * ddist, start + (d + 1) * ddist);
+                    *c = s.trim().parse()
+                        .map_err(|_| parse_error(n, format!("invalid {} '{}'", what, s)))?;
+                }
+                Ok(r)
+            };
+            x.push(vector(20, "position")?);
+            if let Some(v) = &mut v {
+                v.push(vector(20 + 3 * ddist, "velocity")?);
+            }
+        }
+
+        let (n, line) = next_line("box")?;
+        let values = line.split_whitespace()
+            .map(|s| s.parse::<c_float>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| parse_error(n, format!("invalid box '{}'", line.trim())))?;
+        let mut gro_box = [0.; 9];
+        match values.len() {
+            3 | 9 => gro_box[..values.len()].copy_from_slice(&values),
+            _ => return Err(parse_error(n, format!("expected 3 or 9 box values, found {}", values.len()))),
+        }
+
+        Ok(Self {
+            title,
+            atoms,
+            x,
+            v,
+            sim_box: box_from_gro(gro_box),
+            // Fields are 5 characters wider than the number of decimal places
+            precision: ddist.saturating_sub(5),
+        })
+    }
+
+    /// Write the `.gro` file to `path`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.to_writer(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the `.gro` file to a stream, in the same format as GROMACS, with positions to
+    /// `precision` decimal places.
+    /// Residue and atom numbers wrap around at 100000, and names are truncated to 5 characters.
+    pub fn to_writer<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let expected = self.atoms.len();
+        for found in [Some(self.x.len()), self.v.as_ref().map(Vec::len)].into_iter().flatten() {
+            if found != expected {
+                return Err(ErrorKind::NatomsMismatch { expected, found }.into())
+            }
+        }
+        writeln!(writer, "{}", self.title)?;
+        writeln!(writer, "{:5}", self.atoms.len())?;
+        for (i, (atom, x)) in self.atoms.iter().zip(&self.x).enumerate() {
+            write!(
+                writer,
+                "{:>5}{:<5.5}{:>5.5}{:>5}{:w$.p$}{:w$.p$}{:w$.p$}",
+                atom.res_number.rem_euclid(100000),
+                atom.res_name,
+                atom.name,
+                atom.number.rem_euclid(100000),
+                x.0[0], x.0[1], x.0[2],
+                w = self.precision + 5,
+                p = self.precision,
+            )?;
+            if let Some(v) = &self.v {
+                let (w, p) = (self.precision + 5, self.precision + 1);
+                write!(writer, "{:w$.p$}{:w$.p$}{:w$.p$}", v[i].0[0], v[i].0[1], v[i].0[2])?;
+            }
+            writeln!(writer)?;
+        }
+        let gro_box = box_to_gro(&self.sim_box);
+        let n = if gro_box[3..].iter().all(|&b| b == 0.) { 3 } else { 9 };
+        for b in &gro_box[..n] {
+            write!(writer, "{:10.5}", b)?;
+        }
+        writeln!(writer)?;
+        Ok(())
+    }
+
+    /// Convert to an xtc frame. The step and time are taken from the title if it contains
+    /// `t=` and `step=`, as written by GROMACS, and are 0 otherwise.
+    pub fn to_xtc_frame(&self) -> XTCFrame {
+        let (step, time) = title_step_time(&self.title);
+        XTCFrame {
+            step: step.unwrap_or(0),
+            time: time.unwrap_or(0.),
+            sim_box: self.sim_box,
+            x: self.x.clone(),
+            ..XTCFrame::empty()
+        }
+    }
+}
+
+fn parse_error(line: usize, message: String) -> Error {
+    ErrorKind::Parse { line, message }.into()
+}
+
+/// Read the step and time from a title containing `step=` and `t=`, as written by GROMACS
+pub(crate) fn title_step_time(title: &str) -> (Option<c_int>, Option<c_float>) {
+    let value = |key: &str| title.split_once(key)
+        .and_then(|(_, rest)| rest.split_whitespace().next());
+    (
+        value("step=").and_then(|s| s.parse().ok()),
+        value("t=").and_then(|s| s.parse().ok()),
+    )
+}
+
+/// Width of each coordinate field, from the distance between the first two decimal points
+/// after the atom numbers
+fn decimal_spacing(line: &str) -> Option<usize> {
+    let coords = line.get(20..)?;
+    let first = coords.find('.')?;
+    let second = coords[first + 1..].find('.')?;
+    Some(second + 1)
+}
+
+/// Convert the 9 numbers of a `.gro` box line to a box matrix. The numbers are ordered
+/// v1(x) v2(y) v3(z) v1(y) v1(z) v2(x) v2(z) v3(x) v3(y), where v1, v2 and v3 are the box
+/// vectors. Boxes with only 3 numbers should have the rest set to 0.
+pub fn box_from_gro(b: [c_float; 9]) -> matrix {
+    matrix([
+        [b[0], b[3], b[4]],
+        [b[5], b[1], b[6]],
+        [b[7], b[8], b[2]],
+    ])
+}
+
+/// Convert a box matrix to the 9 numbers of a `.gro` box line. See [`box_from_gro`].
+pub fn box_to_gro(m: &matrix) -> [c_float; 9] {
+    let m = &m.0;
+    [m[0][0], m[1][1], m[2][2], m[0][1], m[0][2], m[1][0], m[1][2], m[2][0], m[2][1]]
+}
@@ -0,0 +1,330 @@
+use std::{
+    collections::BTreeMap,
+    ffi::{c_int, c_uint, c_float},
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    Error, XDRFile, access_mode,
+    frames::{FrameHeader, read_header},
+    xdr::*,
+    xtc::*,
+};
+
+/// Magic number identifying a frame index sidecar file
+const INDEX_MAGIC: c_int = 0x58494458;
+const INDEX_VERSION: c_int = 1;
+
+/// Byte offsets of the frames in an xtc file, along with the size and modification time of the
+/// file they were built from, so a saved index can be checked before it is reused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameIndex {
+    /// Byte offset of the start of each frame
+    pub offsets: Vec<u64>,
+    /// Size of the indexed file in bytes
+    pub file_size: u64,
+    /// Modification time of the indexed file, as seconds and nanoseconds since the unix epoch
+    pub mtime: (u64, u32),
+}
+
+impl FrameIndex {
+    /// Default location of the sidecar index for the trajectory at `path`, which is the same
+    /// path with `.idx` appended.
+    pub fn sidecar_path<P: AsRef<Path>>(path: P) -> PathBuf {
+        let mut sidecar = path.as_ref().as_os_str().to_owned();
+        sidecar.push(".idx");
+        sidecar.into()
+    }
+
+    /// Number of frames in the index
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Whether the index contains no frames
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Check whether this index was built from a file with the given metadata
+    pub fn matches(&self, metadata: &std::fs::Metadata) -> bool {
+        self.file_size == metadata.len() && self.mtime == mtime(metadata)
+    }
+
+    /// Save the index to a sidecar file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut xd = XDRFILE::open(path, b'w' as _)?;
+        if xd.write_int(&[INDEX_MAGIC, INDEX_VERSION]) != 2
+            || !write_u64(&mut xd, self.file_size)
+            || !write_u64(&mut xd, self.mtime.0)
+            || xd.write_uint(&[self.mtime.1]) != 1
+            || !write_u64(&mut xd, self.offsets.len() as u64)
+            || !self.offsets.iter().all(|&offset| write_u64(&mut xd, offset))
+        {
+            return Err(XDRStatus::exdrINT.into())
+        }
+        match xd.close() {
+            0 => Ok(()),
+            _ => Err(XDRStatus::exdrCLOSE.into()),
+        }
+    }
+
+    /// Load an index from a sidecar file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut xd = XDRFILE::open(path, b'r' as _)?;
+        let mut header = [0; 2];
+        if xd.read_int(&mut header) != 2 { return Err(XDRStatus::exdrHEADER.into()) }
+        if header[0] != INDEX_MAGIC { return Err(XDRStatus::exdrMAGIC.into()) }
+        if header[1] != INDEX_VERSION { return Err(XDRStatus::exdrHEADER.into()) }
+
+        let file_size = read_u64(&mut xd).ok_or(XDRStatus::exdrINT)?;
+        let mtime_secs = read_u64(&mut xd).ok_or(XDRStatus::exdrINT)?;
+        let mut mtime_nanos = [0];
+        if xd.read_uint(&mut mtime_nanos) != 1 { return Err(XDRStatus::exdrINT.into()) }
+        let nframes = read_u64(&mut xd).ok_or(XDRStatus::exdrINT)?;
+        // Every frame is at least a header long, so this guards against silly allocations
+        if nframes > file_size { return Err(XDRStatus::exdrHEADER.into()) }
+
+        let offsets = (0..nframes)
+            .map(|_| read_u64(&mut xd))
+            .collect::<Option<Vec<_>>>()
+            .ok_or(XDRStatus::exdrINT)?;
+        Ok(Self {
+            offsets,
+            file_size,
+            mtime: (mtime_secs, mtime_nanos[0]),
+        })
+    }
+}
+
+impl XDRFile<access_mode::Read> {
+    /// Scan the headers of every frame in an xtc file to build an index of frame offsets,
+    /// replacing any existing index. The coordinates are skipped without being decompressed.
+    ///
+    /// A truncated frame at the end of the file is not included in the index.
+    /// The current position in the file is preserved.
+    pub fn build_index(&mut self) -> Result<&FrameIndex, Error> {
+        let file_size = self.handle.stream_len()?;
+        // Only files have a modification time to check a saved index against
+        let mtime = self.handle.metadata().map_or((0, 0), |metadata| mtime(&metadata));
+        let offsets = self.scan_headers()?.iter().map(|header| header.offset).collect();
+        Ok(self.index.insert(FrameIndex {
+            offsets,
+            file_size,
+            mtime,
+        }))
+    }
+
+    /// Load the frame index from the sidecar file at `path` if it matches the size and
+    /// modification time of this file. Otherwise, build the index and save it to `path`.
+    ///
+    /// See [`FrameIndex::sidecar_path`] for the default sidecar location.
+    pub fn load_index<P: AsRef<Path>>(&mut self, path: P) -> Result<&FrameIndex, Error> {
+        let metadata = self.handle.metadata()?;
+        match FrameIndex::load(&path) {
+            Ok(index) if index.matches(&metadata) => Ok(self.index.insert(index)),
+            _ => {
+                self.build_index()?.save(path)?;
+                Ok(self.index.as_ref().unwrap())
+            }
+        }
+    }
+
+    /// Get the frame index, building it if it doesn't exist yet
+    pub fn index(&mut self) -> Result<&FrameIndex, Error> {
+        if self.index.is_none() {
+            self.build_index()?;
+        }
+        Ok(self.index.as_ref().unwrap())
+    }
+
+    /// Number of complete frames in an xtc file. Builds the frame index if needed.
+    pub fn frame_count(&mut self) -> Result<usize, Error> {
+        Ok(self.index()?.len())
+    }
+
+    /// Move to the start of frame `n` (counting from 0) of an xtc file, so that it is the next
+    /// frame read. Builds the frame index if needed.
+    /// Returns an end of file error if there are not enough frames in the file.
+    pub fn seek_frame(&mut self, n: usize) -> Result<(), Error> {
+        let Some(&offset) = self.index()?.offsets.get(n) else {
+            return Err(Error::from(XDRStatus::exdrENDOFFILE).at(Some(n), None))
+        };
+        match self.handle.seek(SeekFrom::Start(offset)) {
+            XDRStatus::exdrOK => {
+                self.frame = Some(n);
+                Ok(())
+            }
+            e => Err(Error::from(e).at(Some(n), Some(offset))),
+        }
+    }
+
+    /// Move to the first frame of an xtc file with a time of at least `t`, so that it is the
+    /// next frame read. This matches the `-b` option of GROMACS tools.
+    ///
+    /// The frame is found by bisection, so only a few frame headers are read. If the frame index
+    /// has been built, its offsets are bisected. Otherwise the file is bisected by byte offset,
+    /// searching forwards from each midpoint for the magic number at the start of the next
+    /// frame, so no index is needed. If the times of the frames sampled along the way are out of
+    /// order, as left by a simulation restart, this falls back to reading the frame headers in
+    /// order from the start of the file. A restart which none of the samples land in isn't
+    /// noticed, in which case the frame found may come after the restart.
+    ///
+    /// Returns the header of the frame found, or an end of file error if there are no frames
+    /// at or after `t`, in which case the position in the file is unchanged.
+    pub fn seek_time(&mut self, t: f32) -> Result<FrameHeader, Error> {
+        let file_size = self.handle.stream_len()?;
+        let index = self.index.take();
+        let found = self.peek_start(|xd| {
+            Ok(match &index {
+                Some(index) => find_time_indexed(xd, t, file_size, &index.offsets)?,
+                None => find_time(xd, t, file_size)?,
+            })
+        });
+        self.index = index;
+        let Some((n, header)) = found? else { return Err(XDRStatus::exdrENDOFFILE.into()) };
+        match self.handle.seek(SeekFrom::Start(header.offset)) {
+            XDRStatus::exdrOK => {
+                self.frame = n;
+                Ok(header)
+            }
+            e => Err(Error::from(e).at(None, Some(header.offset))),
+        }
+    }
+}
+
+/// Frame found by [`XDRFile::seek_time`], along with its index if it is known
+type FoundFrame = Option<(Option<usize>, FrameHeader)>;
+
+/// Record the time of the frame sampled at `offset`.
+/// Returns false if it is out of order with the frames sampled before it.
+fn sample(samples: &mut BTreeMap<u64, c_float>, offset: u64, time: c_float) -> bool {
+    let before = samples.range(..offset).next_back().map(|(_, &time)| time);
+    let after = samples.range(offset..).next().map(|(_, &time)| time);
+    samples.insert(offset, time);
+    !before.is_some_and(|before| before > time) && !after.is_some_and(|after| after < time)
+}
+
+/// Find the first frame with a time of at least `t` by bisecting the frame offsets of an index
+fn find_time_indexed(xd: &mut XDRFILE, t: c_float, file_size: u64, offsets: &[u64]) -> Result<FoundFrame, XDRStatus> {
+    let read_at = |xd: &mut XDRFILE, offset| {
+        if xd.seek(SeekFrom::Start(offset)) != XDRStatus::exdrOK { return Err(XDRStatus::exdrNR) }
+        read_header(xd, Some(file_size))
+    };
+    let mut samples = BTreeMap::new();
+    let mut lo = 0;
+    let mut hi = offsets.len();
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        let header = read_at(xd, offsets[mid])?;
+        if !sample(&mut samples, header.offset, header.time) {
+            return scan_time(xd, t, file_size)
+        }
+        if header.time < t {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    let Some(&offset) = offsets.get(lo) else { return Ok(None) };
+    Ok(Some((Some(lo), read_at(xd, offset)?)))
+}
+
+/// Find the first frame with a time of at least `t` by bisecting the file by byte offset
+fn find_time(xd: &mut XDRFILE, t: c_float, file_size: u64) -> Result<FoundFrame, XDRStatus> {
+    // Frames starting before `lo` have times less than `t`, and `lo` is always the start of a
+    // frame. Frames starting at or after `hi` have times of at least `t`.
+    let mut lo = 0;
+    let mut hi = file_size;
+    let mut samples = BTreeMap::new();
+    while lo < hi {
+        let mid = (lo + (hi - lo) / 2) & !3;
+        let Some((header, end)) = sync(xd, mid, hi, file_size)? else {
+            hi = mid;
+            continue
+        };
+        if !sample(&mut samples, header.offset, header.time) {
+            return scan_time(xd, t, file_size)
+        }
+        if header.time < t {
+            lo = end;
+        } else {
+            hi = header.offset;
+        }
+    }
+
+    if xd.seek(SeekFrom::Start(lo)) != XDRStatus::exdrOK { return Err(XDRStatus::exdrNR) }
+    match read_header(xd, Some(file_size)) {
+        Ok(header) if header.time >= t => Ok(Some((None, header))),
+        // Times are out of order
+        Ok(_) => scan_time(xd, t, file_size),
+        Err(XDRStatus::exdrENDOFFILE) => Ok(None),
+        // Data stopped part way through the last frame
+        Err(_) if xd.position() >= file_size => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Find the first frame starting between `pos` and `end` by searching for the magic number.
+/// A candidate is only accepted if it is a complete frame followed either by the end of the file
+/// or by another magic number, which rules out the magic number turning up by chance in the
+/// compressed coordinates.
+/// Returns the frame header and the offset of the end of the frame.
+fn sync(xd: &mut XDRFILE, pos: u64, end: u64, file_size: u64) -> Result<Option<(FrameHeader, u64)>, XDRStatus> {
+    if xd.seek(SeekFrom::Start(pos)) != XDRStatus::exdrOK { return Err(XDRStatus::exdrNR) }
+    let mut p = pos;
+    while p < end {
+        let mut magic = [0];
+        if xd.read_int(&mut magic) != 1 { return Ok(None) }
+        if is_magic(magic[0]) {
+            if xd.seek(SeekFrom::Start(p)) != XDRStatus::exdrOK { return Err(XDRStatus::exdrNR) }
+            if let Ok(header) = read_header(xd, Some(file_size)) {
+                let frame_end = xd.position();
+                let mut next = [0];
+                if frame_end == file_size || (xd.read_int(&mut next) == 1 && is_magic(next[0])) {
+                    return Ok(Some((header, frame_end)))
+                }
+            }
+            if xd.seek(SeekFrom::Start(p + 4)) != XDRStatus::exdrOK { return Err(XDRStatus::exdrNR) }
+        }
+        p += 4;
+    }
+    Ok(None)
+}
+
+/// Find the first frame with a time of at least `t` by reading every frame header in order from
+/// the start of the file
+fn scan_time(xd: &mut XDRFILE, t: c_float, file_size: u64) -> Result<FoundFrame, XDRStatus> {
+    if xd.seek(SeekFrom::Start(0)) != XDRStatus::exdrOK { return Err(XDRStatus::exdrNR) }
+    let mut n = 0;
+    loop {
+        match read_header(xd, Some(file_size)) {
+            Ok(header) if header.time >= t => return Ok(Some((Some(n), header))),
+            Ok(_) => n += 1,
+            Err(XDRStatus::exdrENDOFFILE) => return Ok(None),
+            // Data stopped part way through the last frame
+            Err(_) if xd.position() >= file_size => return Ok(None),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Modification time of a file, as seconds and nanoseconds since the unix epoch
+fn mtime(metadata: &std::fs::Metadata) -> (u64, u32) {
+    metadata.modified().ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or((0, 0), |d| (d.as_secs(), d.subsec_nanos()))
+}
+
+// NOTE: XDR has no 64-bit integer routines, so 64-bit values are stored as two unsigned integers
+fn write_u64(xd: &mut XDRFILE, value: u64) -> bool {
+    xd.write_uint(&[(value >> 32) as c_uint, value as c_uint]) == 2
+}
+
+fn read_u64(xd: &mut XDRFILE) -> Option<u64> {
+    let mut value = [0; 2];
+    if xd.read_uint(&mut value) != 2 { return None }
+    Some(((value[0] as u64) << 32) | value[1] as u64)
+}
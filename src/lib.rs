@@ -1,169 +1,485 @@
 use std::{
     marker::PhantomData,
-    ffi::{CStr, c_char, c_int, c_float},
-    mem::MaybeUninit, ptr::addr_of_mut
+    ffi::{CStr, c_char, c_int, c_float, c_double},
+    io::{self, Read, Write, Seek, SeekFrom},
+    path::Path,
 };
 
 pub mod xdr;
 pub mod xtc;
+pub mod trr;
+pub mod error;
+pub mod frames;
+pub mod index;
+pub mod selection;
+pub mod ndx;
+pub mod gro;
+pub mod pdb;
+pub mod primitive;
+pub mod codec;
+pub mod edr;
+pub mod recover;
+pub mod check;
+pub mod follow;
+pub mod resume;
+#[cfg(feature = "serde")]
+pub mod serde;
 
 use xdr::*;
 use xtc::*;
+use trr::*;
+pub use error::{Error, ErrorKind};
+
+/// Largest number of elements to allocate space for up front when the count is read from a
+/// file. A damaged file can give any count, so larger collections grow as elements are read.
+pub(crate) const MAX_PREALLOC: usize = 4096;
+
+// Lets the derive macros refer to `::xdrfile` from within this crate
+#[cfg(feature = "derive")]
+extern crate self as xdrfile;
 
 pub mod prelude {
     pub use super::xdr::XDRStatus;
     pub use super::xdr::rvec;
+    pub use super::xdr::dvec;
     pub use super::xdr::matrix;
     pub use super::xdr::DIM;
     pub use super::XDRFile;
     pub use super::XTCFrame;
+    pub use super::XTCFrameF64;
+    pub use super::TRRFrame;
     pub use super::access_mode;
+    pub use super::error::{Error, ErrorKind};
+    pub use super::frames::{FrameHeader, XTCFrames, XTCHeaders, XTCReader};
+    pub use super::index::FrameIndex;
+    pub use super::recover::{RecoverFrames, Recovered, SkippedBytes};
+    pub use super::check::{CheckReport, NatomsChange, TimeGap, TrajectoryFormat};
+    pub use super::follow::XTCFollow;
+    pub use super::resume::ResumeInfo;
+    pub use super::selection::Selection;
+    pub use super::ndx::{IndexFile, IndexGroup};
+    pub use super::gro::{GroAtom, GroFile};
+    pub use super::pdb::{PdbAtom, PdbFile, PdbModel, PdbReader};
+    pub use super::codec::{XdrDecode, XdrEncode};
+    pub use super::edr::{EdrReader, Energy, EnergyBlock, EnergyFrame, EnergySeries, EnergySubBlock, EnergyTerm};
 }
 
 pub struct XDRFile<MODE: XDRAccessMode> {
-    handle: *mut XDRFILE,
+    handle: XDRFILE,
+    index: Option<index::FrameIndex>,
+    /// Index of the next frame to be read or written, if known
+    frame: Option<usize>,
+    /// Number of atoms in the first frame written, or in the existing file when appending
+    natoms: Option<usize>,
+    /// Whether frames may be written with different numbers of atoms
+    variable_natoms: bool,
     _mode: PhantomData<MODE>,
 }
 
 impl<MODE: XDRAccessMode> XDRFile<MODE> {
     /// Open an xdr file in the specified access mode.
-    pub fn open(fname: &CStr) -> Result<Self, XDRStatus> {
-        let handle = unsafe {xdrfile_open(fname.as_ptr(), MODE::mode_char()) };
-        if handle.is_null() { return Err(XDRStatus::exdrFILENOTFOUND) }
-        Ok(Self {
+    pub fn open(fname: &CStr) -> Result<Self, Error> {
+        // File names are arbitrary bytes on unix, but must be valid unicode elsewhere
+        #[cfg(unix)]
+        let path: &std::ffi::OsStr = std::os::unix::ffi::OsStrExt::from_bytes(fname.to_bytes());
+        #[cfg(not(unix))]
+        let Ok(path) = fname.to_str() else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "file name is not valid UTF-8").into())
+        };
+        Self::open_path(path)
+    }
+
+    /// Open an xdr file at `path` in the specified access mode.
+    /// When appending, the number of atoms in the first frame of an existing xtc or trr file is
+    /// read, so frames with a different number of atoms are rejected.
+    /// Returns the underlying OS error if the file can't be opened.
+    pub fn open_path<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut file = Self::from_handle(XDRFILE::open(path, *MODE::mode_char())?);
+        if *MODE::mode_char() == 'a' as c_char {
+            let mut natoms = 0;
+            if xtc::read_xtc_natoms(path, &mut natoms) == XDRStatus::exdrOK
+                || trr::read_trr_natoms(path, &mut natoms) == XDRStatus::exdrOK
+            {
+                file.natoms = usize::try_from(natoms).ok();
+            }
+        }
+        Ok(file)
+    }
+
+    fn from_handle(handle: XDRFILE) -> Self {
+        Self {
             handle,
-            _mode: PhantomData::default(),
-        })
+            index: None,
+            // Existing frames aren't counted when appending
+            frame: if *MODE::mode_char() == 'a' as c_char { None } else { Some(0) },
+            natoms: None,
+            variable_natoms: false,
+            _mode: PhantomData,
+        }
+    }
+
+    /// Flush any buffered data and return the underlying stream, which must be of type `T`.
+    /// This is a `std::fs::File` if the file was opened from a path, or the stream that was
+    /// passed in otherwise.
+    pub fn into_inner<T: 'static>(self) -> Result<T, Error> {
+        Ok(self.handle.into_inner()?)
     }
 
     pub fn close(self) {
-        // Return value is the return value of C fclose() or exdrCLOSE if the file pointer is null.
-        // File pointer can't be null here, and I can't think of a case where the value from
-        // fclose() will be useful.
-        unsafe { xdrfile_close(self.handle) };
+        // Return value indicates whether buffered data could be flushed. Dropping the file
+        // flushes anyway, so there's nothing useful to do with it here.
+        self.handle.close();
+    }
+
+    /// Current byte offset in the file, if it can be determined
+    fn offset(&mut self) -> Option<u64> {
+        Some(self.handle.position())
+    }
+
+    /// Build an error for the frame starting at byte `offset`
+    fn error_at(&self, kind: impl Into<ErrorKind>, offset: Option<u64>) -> Error {
+        Error::new(kind.into()).at(self.frame, offset)
     }
-}
 
-impl<MODE: XDRAccessMode> Drop for XDRFile<MODE> {
-    fn drop(&mut self) {
-        unsafe { xdrfile_close(self.handle) };
+    /// Convert the status of reading/writing the frame starting at byte `offset`, moving on to
+    /// the next frame if it succeeded
+    fn frame_result(&mut self, result: XDRStatus, offset: Option<u64>) -> Result<(), Error> {
+        if result != XDRStatus::exdrOK {
+            return Err(self.error_at(result, offset))
+        }
+        self.frame = self.frame.map(|n| n + 1);
+        Ok(())
     }
 }
 
 // TODO: Implement other read/write functions in the xdrfile library
 impl<MODE: XDRAccessMode + access_mode::Writable> XDRFile<MODE> {
+    /// Write to a seekable stream, such as a `Cursor<Vec<u8>>`, starting at its current position
+    pub fn from_writer<W: Write + Seek + 'static>(writer: W) -> Result<Self, Error> {
+        Ok(Self::from_handle(XDRFILE::from_writer(writer)?))
+    }
+
+    /// Write to a stream that can't seek, such as a pipe, socket or stdout
+    pub fn from_unseekable_writer<W: Write + 'static>(writer: W) -> Self {
+        Self::from_handle(XDRFILE::from_unseekable_writer(writer))
+    }
+
+    /// Write xtc frames with the magic number introduced in GROMACS 2023, which stores the size
+    /// of the compressed coordinates as a 64-bit integer. Otherwise, it is only used for frames
+    /// with more than [`MAX_NATOMS_1995`](xtc::MAX_NATOMS_1995) atoms. Files written with it
+    /// can't be read by GROMACS versions before 2023.
+    pub fn set_xtc_2023(&mut self, always: bool) {
+        self.handle.force_xtc_2023 = always;
+    }
+
+    /// Allow frames to be written with different numbers of atoms. Otherwise, every frame must
+    /// have the same number of atoms as the first frame written, or as the existing file when
+    /// appending, since readers usually read the number of atoms once with
+    /// [`XDRFile::read_xtc_natoms`].
+    pub fn set_variable_natoms(&mut self, allow: bool) {
+        self.variable_natoms = allow;
+    }
+
+    /// Check that a frame with `natoms` atoms can be written, returning an error of kind
+    /// `ErrorKind::NatomsMismatch` if it doesn't match the earlier frames
+    fn check_natoms(&self, natoms: usize, offset: Option<u64>) -> Result<(), Error> {
+        match self.natoms {
+            Some(expected) if expected != natoms && !self.variable_natoms => {
+                Err(self.error_at(ErrorKind::NatomsMismatch { expected, found: natoms }, offset))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Convert the status of writing a frame with `natoms` atoms, recording the number of atoms
+    /// if it is the first frame
+    fn write_result(&mut self, result: XDRStatus, natoms: usize, offset: Option<u64>) -> Result<(), Error> {
+        self.frame_result(result, offset)?;
+        self.natoms.get_or_insert(natoms);
+        Ok(())
+    }
+
     /// Write a frame to an xtc file, including the set of atom locations (`x`) the `step`, `time`,
     /// and precision (`prec`).
-    /// Returns `Err(XDRStatus::exdrUINT)` if the length of `x` is too large to be safely converted
-    /// to a `c_int`.
-    pub fn write_xtc(&self, step: i32, time: f32, sim_box: matrix, x: &[rvec], prec: f32) -> Result<(), XDRStatus> {
-        let Ok(natoms) = x.len().try_into() else { return Err(XDRStatus::exdrUINT) };
-        let result = unsafe {
-            write_xtc(
-                self.handle,
-                natoms,
-                step as c_int,
-                time as c_float,
-                sim_box.0.as_ptr(),
-                x.as_ptr(),
-                prec as c_float
-            )
-        };
-        if result != XDRStatus::exdrOK {
-            return Err(result)
+    /// Returns an error of kind `ErrorKind::Overflow` if the length of `x` is too large to be
+    /// safely converted to a `c_int`, or `ErrorKind::NatomsMismatch` if it differs from the
+    /// number of atoms in earlier frames. See [`XDRFile::set_variable_natoms`].
+    pub fn write_xtc(&mut self, step: i32, time: f32, sim_box: matrix, x: &[rvec], prec: f32) -> Result<(), Error> {
+        let offset = self.offset();
+        if c_int::try_from(x.len()).is_err() {
+            return Err(self.error_at(ErrorKind::Overflow, offset))
+        }
+        self.check_natoms(x.len(), offset)?;
+        let result = write_xtc(
+            &mut self.handle,
+            step as c_int,
+            time as c_float,
+            &sim_box,
+            x,
+            prec as c_float
+        );
+        self.write_result(result, x.len(), offset)
+    }
+
+    /// Write a frame of double precision coordinates to an xtc file. The coordinates are
+    /// compressed directly from `f64`, without making a single precision copy of the frame.
+    /// Returns the same errors as [`XDRFile::write_xtc`].
+    pub fn write_xtc_f64(&mut self, step: i32, time: f32, sim_box: matrix, x: &[dvec], prec: f32) -> Result<(), Error> {
+        let offset = self.offset();
+        if c_int::try_from(x.len()).is_err() {
+            return Err(self.error_at(ErrorKind::Overflow, offset))
+        }
+        self.check_natoms(x.len(), offset)?;
+        let result = write_xtc_double(
+            &mut self.handle,
+            step as c_int,
+            time as c_float,
+            &sim_box,
+            x,
+            prec as c_double
+        );
+        self.write_result(result, x.len(), offset)
+    }
+
+    /// Write a frame to a trr file, including the `step`, `time`, `lambda` and box, as well as
+    /// any of the atom positions (`x`), velocities (`v`) and forces (`f`).
+    /// Returns an error of kind `ErrorKind::NatomsMismatch` if `x`, `v` and `f` don't all have
+    /// the same length, or if it differs from the number of atoms in earlier frames. Frames
+    /// without any of them aren't checked.
+    #[allow(clippy::too_many_arguments)]
+    pub fn write_trr(
+        &mut self,
+        step: i32,
+        time: f32,
+        lambda: f32,
+        sim_box: matrix,
+        x: Option<&[rvec]>,
+        v: Option<&[rvec]>,
+        f: Option<&[rvec]>
+    ) -> Result<(), Error> {
+        let offset = self.offset();
+        let mut lens = [x, v, f].into_iter().flatten().map(|p| p.len());
+        let natoms = lens.next();
+        if let Some(expected) = natoms {
+            if let Some(found) = lens.find(|&l| l != expected) {
+                return Err(self.error_at(ErrorKind::NatomsMismatch { expected, found }, offset))
+            }
+            if c_int::try_from(expected).is_err() {
+                return Err(self.error_at(ErrorKind::Overflow, offset))
+            }
+            self.check_natoms(expected, offset)?;
+        }
+        let result = write_trr(
+            &mut self.handle,
+            step as c_int,
+            time as c_float,
+            lambda as c_float,
+            Some(&sim_box),
+            x,
+            v,
+            f
+        );
+        match natoms {
+            Some(natoms) => self.write_result(result, natoms, offset),
+            None => self.frame_result(result, offset),
         }
-        Ok(())
     }
 }
 
 impl XDRFile<access_mode::Read> {
+    /// Read from a seekable stream, such as a `Cursor<Vec<u8>>`, starting at its current position
+    pub fn from_reader<R: Read + Seek + 'static>(reader: R) -> Result<Self, Error> {
+        Ok(Self::from_handle(XDRFILE::from_reader(reader)?))
+    }
+
+    /// Read from a stream that can't seek, such as a pipe, socket or stdin.
+    /// Frames can be read in order, but functions which need to look at other parts of the
+    /// file, such as [`XDRFile::read_xtc_natoms`] and [`XDRFile::seek_frame`], will fail.
+    pub fn from_unseekable_reader<R: Read + 'static>(reader: R) -> Self {
+        Self::from_handle(XDRFILE::from_unseekable_reader(reader))
+    }
+
     /// Read the number of atoms from an xtc file
-    pub fn read_xtc_natoms(&self) -> Result<usize, XDRStatus> {
+    pub fn read_xtc_natoms(&mut self) -> Result<usize, Error> {
+        let natoms = self.peek_start(|xd| {
+            let mut natoms: c_int = 0;
+            let mut step: c_int = 0;
+            let mut time: c_float = 0.;
+            match xtc_header(xd, &mut natoms, &mut step, &mut time, true) {
+                XDRStatus::exdrOK => Ok(natoms),
+                e => Err(Error::from(e).at(Some(0), Some(0))),
+            }
+        })?;
+
+        // Make sure natoms is non-negative and return it
+        let Ok(natoms) = natoms.try_into() else {
+            return Err(XDRStatus::exdrHEADER.into())
+        };
+        Ok(natoms)
+    }
+
+    /// Read the number of atoms from a trr file
+    pub fn read_trr_natoms(&mut self) -> Result<usize, Error> {
+        let natoms = self.peek_start(|xd| {
+            let mut sh = TRRHeader::default();
+            match trr_header(xd, &mut sh, true) {
+                XDRStatus::exdrOK => Ok(sh.natoms),
+                e => Err(Error::from(e).at(Some(0), Some(0))),
+            }
+        })?;
+
+        // Make sure natoms is non-negative and return it
+        let Ok(natoms) = natoms.try_into() else {
+            return Err(XDRStatus::exdrHEADER.into())
+        };
+        Ok(natoms)
+    }
+
+    /// Run `f` from the start of the file, then return to the current position
+    fn peek_start<T>(&mut self, f: impl FnOnce(&mut XDRFILE) -> Result<T, Error>) -> Result<T, Error> {
+        // Data read from a pipe can't be read again
+        if !self.handle.can_seek() {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "stream can't seek").into())
+        }
+
         // Save current position
-        let fpos = unsafe { xdr_tell(self.handle) };
+        let fpos = self.handle.position();
 
-        // Go to start of file (libxdrfile implementation takes a file name and opens it fresh)
-        match unsafe { xdr_seek(self.handle, 0, libc::SEEK_SET) } {
+        // Go to start of file
+        match self.handle.seek(SeekFrom::Start(0)) {
             XDRStatus::exdrOK => (),
-            e => return Err(e),
-        }
-
-        // Read header to get numatoms
-        let mut natoms = MaybeUninit::<c_int>::uninit();
-        let mut step = MaybeUninit::<c_int>::uninit();
-        let mut time = MaybeUninit::<c_float>::uninit();
-        match unsafe {
-            xtc_header(
-                self.handle,
-                natoms.as_mut_ptr(),
-                step.as_mut_ptr(),
-                time.as_mut_ptr(),
-                mybool::TRUE
-            )
-        } {
-            XDRStatus::exdrOK => (),
-            e => return Err(e),
+            e => return Err(e.into()),
         }
 
+        let result = f(&mut self.handle);
+
         // Jump back to old file position
-        match unsafe { xdr_seek(self.handle, fpos, libc::SEEK_SET) } {
-            XDRStatus::exdrOK => (),
-            e => return Err(e),
+        match self.handle.seek(SeekFrom::Start(fpos)) {
+            XDRStatus::exdrOK => result,
+            e => Err(e.into()),
         }
+    }
 
-        // Make sure natoms is non-negative and return it
-        let Ok(natoms) = unsafe { natoms.assume_init() }.try_into() else {
-            return Err(XDRStatus::exdrUINT)
-        };
-        Ok(natoms)
+    /// Read a frame from an xtc file.
+    /// Returns an error of kind `ErrorKind::NatomsMismatch` if the frame doesn't contain
+    /// `natoms` atoms.
+    pub fn read_xtc_reuse(&mut self, natoms: usize, frame: &mut XTCFrame) -> Result<(), Error> {
+        let offset = self.offset();
+        let file_natoms = self.read_xtc_header_checked(natoms, &mut frame.step, &mut frame.time, offset)?;
+        frame.x.resize(natoms, rvec::new());
+        let result = xtc_coord(
+            &mut self.handle,
+            file_natoms,
+            &mut frame.sim_box,
+            &mut frame.x,
+            &mut frame.prec
+        );
+        self.frame_result(result, offset)
     }
 
-    /// Read a frame from an xtc file
-    pub fn read_xtc_reuse(&self, natoms: usize, frame: &mut XTCFrame) -> Result<(), XDRStatus> {
-        let Ok(num_atoms) = natoms.try_into() else { return Err(XDRStatus::exdrUINT) };
-        frame.x.clear();
-        frame.x.reserve(natoms);
-        let result = unsafe {
-            read_xtc(
-                self.handle,
-                num_atoms,
-                &mut frame.step,
-                &mut frame.time,
-                frame.sim_box.0.as_mut_ptr(),
-                frame.x.as_mut_ptr(),
-                &mut frame.prec
-            )
-        };
-        unsafe { frame.x.set_len(natoms); }
+    /// Read a frame from an xtc file into double precision coordinates, which are decompressed
+    /// directly to `f64`.
+    /// Returns an error of kind `ErrorKind::NatomsMismatch` if the frame doesn't contain
+    /// `natoms` atoms.
+    pub fn read_xtc_f64_reuse(&mut self, natoms: usize, frame: &mut XTCFrameF64) -> Result<(), Error> {
+        let offset = self.offset();
+        let file_natoms = self.read_xtc_header_checked(natoms, &mut frame.step, &mut frame.time, offset)?;
+        frame.x.resize(natoms, dvec::new());
+        let mut prec = frame.prec as c_double;
+        let result = xtc_coord_double(
+            &mut self.handle,
+            file_natoms,
+            &mut frame.sim_box,
+            &mut frame.x,
+            &mut prec
+        );
+        frame.prec = prec as c_float;
+        self.frame_result(result, offset)
+    }
 
-        if result != XDRStatus::exdrOK { return Err(result) }
+    /// Read a frame from an xtc file into double precision coordinates
+    pub fn read_xtc_f64(&mut self, natoms: usize) -> Result<XTCFrameF64, Error> {
+        let mut frame = XTCFrameF64::empty();
+        self.read_xtc_f64_reuse(natoms, &mut frame)?;
+        Ok(frame)
+    }
 
-        Ok(())
+    /// Read an xtc frame header, checking that the frame contains `natoms` atoms
+    fn read_xtc_header_checked(
+        &mut self,
+        natoms: usize,
+        step: &mut c_int,
+        time: &mut c_float,
+        offset: Option<u64>
+    ) -> Result<c_int, Error> {
+        let mut file_natoms: c_int = 0;
+        let result = xtc_header(&mut self.handle, &mut file_natoms, step, time, true);
+        if result != XDRStatus::exdrOK {
+            return Err(self.error_at(result, offset))
+        }
+        if file_natoms as usize != natoms || file_natoms < 0 {
+            let kind = match usize::try_from(file_natoms) {
+                Ok(found) => ErrorKind::NatomsMismatch { expected: natoms, found },
+                Err(_) => XDRStatus::exdrHEADER.into(),
+            };
+            return Err(self.error_at(kind, offset))
+        }
+        Ok(file_natoms)
     }
 
     /// Read a frame from an xtc file
-    pub fn read_xtc(&self, natoms: usize) -> Result<XTCFrame, XDRStatus> {
-        let Ok(num_atoms) = natoms.try_into() else { return Err(XDRStatus::exdrUINT) };
-        let mut frame = MaybeUninit::<XTCFrame>::uninit();
-        let result = unsafe {
-            let f = frame.as_mut_ptr();
-            addr_of_mut!((*f).sim_box).write(matrix::new());
-            addr_of_mut!((*f).x).write(Vec::with_capacity(natoms));
-            (*f).x.set_len(num_atoms as usize);
-            read_xtc(
-                self.handle,
-                num_atoms,
-                &mut (*f).step,
-                &mut (*f).time,
-                (*f).sim_box.0.as_mut_ptr(),
-                (*f).x.as_mut_ptr(),
-                &mut (*f).prec
-            )
-        };
+    pub fn read_xtc(&mut self, natoms: usize) -> Result<XTCFrame, Error> {
+        let mut frame = XTCFrame::empty();
+        self.read_xtc_reuse(natoms, &mut frame)?;
+        Ok(frame)
+    }
 
-        if result != XDRStatus::exdrOK { return Err(result) }
+    /// Read a frame from a trr file. Each of `frame.x`, `frame.v` and `frame.f` is set to
+    /// `None` if it is not present in the frame, and is otherwise resized to `natoms`.
+    /// Returns an error of kind `ErrorKind::NatomsMismatch` if the frame doesn't contain
+    /// `natoms` atoms.
+    pub fn read_trr_reuse(&mut self, natoms: usize, frame: &mut TRRFrame) -> Result<(), Error> {
+        let offset = self.offset();
+        let mut sh = TRRHeader::default();
+        let result = trr_header(&mut self.handle, &mut sh, true);
+        if result != XDRStatus::exdrOK {
+            return Err(self.error_at(result, offset))
+        }
+        if sh.natoms as usize != natoms || sh.natoms < 0 {
+            let kind = match usize::try_from(sh.natoms) {
+                Ok(found) => ErrorKind::NatomsMismatch { expected: natoms, found },
+                Err(_) => XDRStatus::exdrHEADER.into(),
+            };
+            return Err(self.error_at(kind, offset))
+        }
+
+        frame.step = sh.step;
+        frame.time = sh.t;
+        frame.lambda = sh.lambda;
+        for (size, p) in [(sh.x_size, &mut frame.x), (sh.v_size, &mut frame.v), (sh.f_size, &mut frame.f)] {
+            if size == 0 {
+                *p = None;
+            } else {
+                p.get_or_insert_with(Vec::new).resize(natoms, rvec::new());
+            }
+        }
+        let result = read_trr_data(
+            &mut self.handle,
+            &sh,
+            &mut frame.sim_box,
+            frame.x.as_deref_mut(),
+            frame.v.as_deref_mut(),
+            frame.f.as_deref_mut()
+        );
+        self.frame_result(result, offset)
+    }
 
-        Ok(unsafe {frame.assume_init()})
+    /// Read a frame from a trr file
+    pub fn read_trr(&mut self, natoms: usize) -> Result<TRRFrame, Error> {
+        let mut frame = TRRFrame::empty();
+        self.read_trr_reuse(natoms, &mut frame)?;
+        Ok(frame)
     }
 }
 
@@ -188,6 +504,54 @@ impl XTCFrame {
     }
 }
 
+/// An xtc frame with double precision coordinates. The box and precision are stored in single
+/// precision in the file, so are kept as `f32`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XTCFrameF64 {
+    pub step: c_int,
+    pub time: c_float,
+    pub sim_box: matrix,
+    pub prec: c_float,
+    pub x: Vec<dvec>,
+}
+
+impl XTCFrameF64 {
+    pub fn empty() -> Self {
+        Self {
+            step: 0,
+            time: 0.,
+            sim_box: matrix::new(),
+            prec: 1000.,
+            x: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TRRFrame {
+    pub step: c_int,
+    pub time: c_float,
+    pub lambda: c_float,
+    pub sim_box: matrix,
+    pub x: Option<Vec<rvec>>,
+    pub v: Option<Vec<rvec>>,
+    pub f: Option<Vec<rvec>>,
+}
+
+impl TRRFrame {
+    pub fn empty() -> Self {
+        Self {
+            step: 0,
+            time: 0.,
+            lambda: 0.,
+            sim_box: matrix::new(),
+            x: None,
+            v: None,
+            f: None,
+        }
+    }
+}
+
 pub mod access_mode {
     pub struct Read;
     pub struct Write;
@@ -227,14 +591,14 @@ mod seal {
 
 
 #[cfg(test)]
+#[allow(clippy::needless_range_loop, clippy::unnecessary_cast)]
 mod tests {
     use std::ffi::{c_char, CString, c_int, c_float};
-    use std::mem::MaybeUninit;
     use super::prelude::*;
 
     #[test]
     /// Test the safe wrapper for reading/writing xtc files
-    fn test_xtc_wrapper() -> Result<(), XDRStatus> {
+    fn test_xtc_wrapper() -> Result<(), Error> {
 
         let test_file = CString::new("tests/test_wrapper.xtc").unwrap();
         let nframes = 13;
@@ -263,7 +627,7 @@ mod tests {
 
         {
             println!("Opening xtc file");
-            let xtc_write = XDRFile::<access_mode::Write>::open(&test_file)?;
+            let mut xtc_write = XDRFile::<access_mode::Write>::open(&test_file)?;
             println!("Writing xtc file");
             for k in 0..nframes {
                 xtc_write.write_xtc(step1 + k, time1 + k as f32, box1, &x1[..], prec1)?;
@@ -272,7 +636,7 @@ mod tests {
 
         {
             println!("Reading xtc file");
-            let xtc_read = XDRFile::<access_mode::Read>::open(&test_file)?;
+            let mut xtc_read = XDRFile::<access_mode::Read>::open(&test_file)?;
             let mut frame = XTCFrame::empty();
             let natoms2 = xtc_read.read_xtc_natoms()?;
             assert_eq!(natoms1, natoms2, "Number of atoms incorrect when reading xtc file");
@@ -295,7 +659,8 @@ mod tests {
 
                 k += 1;
             }
-            assert_eq!(xtc_read.read_xtc(natoms2), Err(XDRStatus::exdrENDOFFILE));
+            assert_eq!(k, nframes, "Incorrect number of frames read");
+            assert!(xtc_read.read_xtc(natoms2).unwrap_err().is_eof());
         }
 
         Ok(())
@@ -307,7 +672,7 @@ mod tests {
         use super::xdr::*;
         use super::xtc::*;
 
-        let test_file = CString::new("tests/test.xtc").unwrap();
+        let test_file = "tests/test.xtc";
         let nframes: c_int = 13;
         let natoms1: c_int = 173;
         let step1: c_int = 1993;
@@ -335,41 +700,34 @@ mod tests {
 
 
         let writemode = 'w' as c_char;
-        let xd = unsafe { xdrfile_open(test_file.as_ptr(), &writemode) };
-
-        assert!(!xd.is_null(), "Error opening xdrfile for writing");
+        let mut xd = XDRFILE::open(test_file, writemode).expect("Error opening xdrfile for writing");
 
         for k in 0..nframes {
-            let result = unsafe {write_xtc(xd, natoms1, step1 + k as c_int, time1 + k as c_float, box1.0.as_mut_ptr(), x1.as_mut_ptr(), prec1)};
+            let result = write_xtc(&mut xd, step1 + k as c_int, time1 + k as c_float, &box1, &x1, prec1);
             assert_eq!(result, XDRStatus::exdrOK, "Error writing xtc file: {:?}", result);
         }
-        unsafe { xdrfile_close(xd) };
+        assert_eq!(xd.close(), 0, "Error closing xdrfile");
 
 
-        let mut natoms2 = MaybeUninit::<c_int>::uninit();
-        let result = unsafe {read_xtc_natoms(test_file.as_ptr(), natoms2.as_mut_ptr() as *mut c_int)};
+        let mut natoms2: c_int = 0;
+        let result = read_xtc_natoms(test_file, &mut natoms2);
         assert_eq!(result, XDRStatus::exdrOK, "Error reading xtc file: {:?}", result);
-        let natoms2 = unsafe {natoms2.assume_init()};
         assert_eq!(natoms1, natoms2, "Number of atoms incorrect when reading xtc file");
 
-        let mut x2 = Vec::<rvec>::with_capacity(natoms2 as usize);
+        let mut x2 = vec![rvec::new(); natoms2 as usize];
 
         let readmode = 'r' as c_char;
-        let xd = unsafe {xdrfile_open(test_file.as_ptr(), &readmode)};
+        let mut xd = XDRFILE::open(test_file, readmode).expect("Error opening xdrfile for reading");
         let mut k = 0;
         let mut result = XDRStatus::exdrOK;
         let mut box2 = matrix::new();
         while result == XDRStatus::exdrOK {
-            let mut step2 = MaybeUninit::<c_int>::uninit();
-            let mut time2 = MaybeUninit::<c_float>::uninit();
-            let mut prec2 = MaybeUninit::<c_float>::uninit();
-            result = unsafe {read_xtc(xd, natoms2, step2.as_mut_ptr(), time2.as_mut_ptr(), box2.0.as_mut_ptr(), x2.as_mut_ptr(), prec2.as_mut_ptr())};
+            let mut step2: c_int = 0;
+            let mut time2: c_float = 0.;
+            let mut prec2: c_float = 0.;
+            result = read_xtc(&mut xd, &mut step2, &mut time2, &mut box2, &mut x2, &mut prec2);
             if result == XDRStatus::exdrENDOFFILE {break}
             assert_eq!(result, XDRStatus::exdrOK, "Error reading frame from xtc file: {:?}", result);
-            let step2 = unsafe {step2.assume_init()};
-            let time2 = unsafe {time2.assume_init()};
-            let prec2 = unsafe {prec2.assume_init()};
-            unsafe {x2.set_len(natoms2 as usize)};
             assert_eq!(step2 - step1, k, "Incorrect step on frame {}", k);
             assert!(f32::abs(time2 - time1 - k as c_float) <= toler, "Incorrect time on frame {}", k);
             assert!(f32::abs(prec2 - prec1) <= toler, "Incorrect precision on frame {}", k);
@@ -386,6 +744,1570 @@ mod tests {
 
             k += 1;
         }
-        unsafe {xdrfile_close(xd)};
+        assert_eq!(k, nframes, "Incorrect number of frames read");
+        xd.close();
+    }
+
+    #[test]
+    /// Check that frames written by libxdrfile are decoded correctly, and that re-encoding
+    /// them gives the exact same bytes.
+    fn test_libxdrfile_compat() -> Result<(), Error> {
+        let ref_file = CString::new("tests/libxdrfile_ref.xtc").unwrap();
+        let test_file = CString::new("tests/test_compat.xtc").unwrap();
+        let natoms1 = 173;
+        let toler = 1e-3;
+
+        let mut frames = Vec::new();
+        {
+            let mut xtc_read = XDRFile::<access_mode::Read>::open(&ref_file)?;
+            let natoms = xtc_read.read_xtc_natoms()?;
+            assert_eq!(natoms, natoms1);
+            loop {
+                match xtc_read.read_xtc(natoms) {
+                    Ok(frame) => frames.push(frame),
+                    Err(e) if e.is_eof() => break,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        assert_eq!(frames.len(), 13);
+        for (k, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.step, 1993 + k as c_int);
+            for (i, x) in frame.x.iter().enumerate() {
+                for j in 0..DIM {
+                    let x1 = (i+1) as c_float * 3.7 as c_float + (j+1) as c_float;
+                    assert!(f32::abs(x.0[j] - x1) <= toler, "Incorrect x on frame {}", k);
+                }
+            }
+        }
+
+        {
+            let mut xtc_write = XDRFile::<access_mode::Write>::open(&test_file)?;
+            for frame in &frames {
+                xtc_write.write_xtc(frame.step, frame.time, frame.sim_box, &frame.x, frame.prec)?;
+            }
+        }
+        let expected = std::fs::read("tests/libxdrfile_ref.xtc").unwrap();
+        let written = std::fs::read("tests/test_compat.xtc").unwrap();
+        assert!(expected == written, "Re-encoded xtc file differs from libxdrfile output");
+
+        Ok(())
+    }
+
+    #[test]
+    /// Round trip clustered (water-like) coordinates, which exercise the run-length encoding,
+    /// as well as small frames which are stored uncompressed.
+    fn test_xtc_roundtrip() -> Result<(), Error> {
+        let test_file = CString::new("tests/test_roundtrip.xtc").unwrap();
+        let toler = 1e-3;
+        let offsets = [[0., 0., 0.], [0.0957, 0., 0.], [-0.024, 0.0927, 0.]];
+        let mut x1 = Vec::new();
+        for i in 0..500 {
+            let centre = [(i % 10) as c_float * 0.31, ((i / 10) % 10) as c_float * 0.29, (i / 100) as c_float * 0.33];
+            for o in offsets {
+                x1.push(rvec([centre[0] + o[0], centre[1] + o[1], centre[2] - o[2] - 1.5]));
+            }
+        }
+        // Add some sparse coordinates to force changes in the small integer size
+        x1.push(rvec([25.3, -13.2, 0.]));
+        x1.push(rvec([0.1, 0.2, 0.3]));
+        let mut sim_box = matrix::new();
+        sim_box.0[0][0] = 3.1;
+        sim_box.0[1][1] = 2.9;
+        sim_box.0[2][2] = 1.65;
+
+        {
+            let mut xtc_write = XDRFile::<access_mode::Write>::open(&test_file)?;
+            xtc_write.set_variable_natoms(true);
+            xtc_write.write_xtc(0, 0., sim_box, &x1, 1000.)?;
+            xtc_write.write_xtc(1, 1., sim_box, &x1[..7], 1000.)?;
+        }
+
+        let mut xtc_read = XDRFile::<access_mode::Read>::open(&test_file)?;
+        let frame = xtc_read.read_xtc(x1.len())?;
+        assert_eq!(frame.sim_box, sim_box);
+        for (i, (x, x2)) in x1.iter().zip(&frame.x).enumerate() {
+            for j in 0..DIM {
+                assert!(f32::abs(x.0[j] - x2.0[j]) <= toler, "Incorrect x for atom {}", i);
+            }
+        }
+        let frame = xtc_read.read_xtc(7)?;
+        assert_eq!(frame.step, 1);
+        assert_eq!(&frame.x[..], &x1[..7]);
+        assert!(xtc_read.read_xtc(7).unwrap_err().is_eof());
+        Ok(())
+    }
+
+    #[test]
+    /// Test the safe wrapper for reading/writing trr files
+    fn test_trr_wrapper() -> Result<(), Error> {
+        let test_file = CString::new("tests/test_wrapper.trr").unwrap();
+        let nframes = 5;
+        let natoms1 = 57;
+
+        let mut box1 = matrix::new();
+        for i in 0..DIM {
+            box1.0[i][i] = (i + 1) as c_float * 2.5;
+        }
+        let make = |scale: c_float| (0..natoms1)
+            .map(|i| rvec([i as c_float * scale, -(i as c_float), scale]))
+            .collect::<Vec<_>>();
+        let (x1, v1, f1) = (make(0.1), make(1.3), make(-7.2));
+
+        {
+            let mut trr_write = XDRFile::<access_mode::Write>::open(&test_file)?;
+            for k in 0..nframes {
+                // Only write velocities/forces on every second frame
+                let (v, f) = if k % 2 == 0 { (Some(&v1[..]), Some(&f1[..])) } else { (None, None) };
+                trr_write.write_trr(k, k as c_float * 0.5, 0.25, box1, Some(&x1), v, f)?;
+            }
+            let err = trr_write.write_trr(nframes, 0., 0., box1, Some(&x1), Some(&v1[1..]), None).unwrap_err();
+            assert!(matches!(err.kind(), ErrorKind::NatomsMismatch { expected: 57, found: 56 }));
+            assert_eq!(err.frame(), Some(nframes as usize));
+        }
+
+        let mut trr_read = XDRFile::<access_mode::Read>::open(&test_file)?;
+        let natoms2 = trr_read.read_trr_natoms()?;
+        assert_eq!(natoms1, natoms2, "Number of atoms incorrect when reading trr file");
+        let mut frame = TRRFrame::empty();
+        for k in 0..nframes {
+            trr_read.read_trr_reuse(natoms2, &mut frame)?;
+            assert_eq!(frame.step, k, "Incorrect step on frame {}", k);
+            assert_eq!(frame.time, k as c_float * 0.5, "Incorrect time on frame {}", k);
+            assert_eq!(frame.lambda, 0.25, "Incorrect lambda on frame {}", k);
+            assert_eq!(frame.sim_box, box1, "Incorrect box on frame {}", k);
+            assert_eq!(frame.x.as_ref(), Some(&x1), "Incorrect x on frame {}", k);
+            if k % 2 == 0 {
+                assert_eq!(frame.v.as_ref(), Some(&v1), "Incorrect v on frame {}", k);
+                assert_eq!(frame.f.as_ref(), Some(&f1), "Incorrect f on frame {}", k);
+            } else {
+                assert_eq!(frame.v, None, "Unexpected v on frame {}", k);
+                assert_eq!(frame.f, None, "Unexpected f on frame {}", k);
+            }
+        }
+        assert!(trr_read.read_trr(natoms2).unwrap_err().is_eof());
+
+        Ok(())
+    }
+
+    #[test]
+    /// Test iterating over xtc frames, including stopping on errors
+    fn test_xtc_frames() -> Result<(), Error> {
+        let test_file = CString::new("tests/test_frames.xtc").unwrap();
+        let x1: Vec<_> = (0..20).map(|i| rvec([i as c_float, 0.5, -0.5])).collect();
+        {
+            let mut xtc_write = XDRFile::<access_mode::Write>::open(&test_file)?;
+            xtc_write.set_variable_natoms(true);
+            for k in 0..4 {
+                xtc_write.write_xtc(k, k as c_float, matrix::new(), &x1[..10 + k as usize], 1000.)?;
+            }
+        }
+
+        let mut xtc_read = XDRFile::<access_mode::Read>::open(&test_file)?;
+        let frames = xtc_read.frames().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(frames.len(), 4);
+        for (k, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.step, k as c_int);
+            assert_eq!(frame.x.len(), 10 + k);
+        }
+        assert_eq!(xtc_read.frames().count(), 0);
+
+        // Truncate the last frame, which should give an error rather than end of file
+        let data = std::fs::read("tests/test_frames.xtc").unwrap();
+        std::fs::write("tests/test_frames.xtc", &data[..data.len() - 8]).unwrap();
+        let mut xtc_read = XDRFile::<access_mode::Read>::open(&test_file)?;
+        let mut reader = xtc_read.reader();
+        let mut k = 0;
+        while let Some(frame) = reader.next() {
+            match frame {
+                Ok(frame) => assert_eq!(frame.step, k),
+                Err(e) => {
+                    assert_eq!(k, 3, "Unexpected error on frame {}", k);
+                    assert_eq!(e, XDRStatus::exdr3DX);
+                    assert_eq!(e.frame(), Some(3));
+                }
+            }
+            k += 1;
+        }
+        assert_eq!(k, 4);
+        assert!(reader.next().is_none());
+
+        // A huge number of atoms in a corrupt header isn't allocated before it runs out of data
+        let mut corrupt = data.clone();
+        for offset in [4, 52] {
+            corrupt[offset..offset + 4].copy_from_slice(&c_int::MAX.to_be_bytes());
+        }
+        let mut xtc_read = XDRFile::<access_mode::Read>::from_reader(std::io::Cursor::new(corrupt))?;
+        assert_eq!(xtc_read.frames().next().unwrap().unwrap_err(), XDRStatus::exdr3DX);
+        Ok(())
+    }
+
+    #[test]
+    /// Test random access to xtc frames using the frame index
+    fn test_xtc_index() -> Result<(), Error> {
+        let test_file = CString::new("tests/test_index.xtc").unwrap();
+        let sidecar = FrameIndex::sidecar_path("tests/test_index.xtc");
+        let _ = std::fs::remove_file(&sidecar);
+        let x1: Vec<_> = (0..50).map(|i| rvec([i as c_float * 0.1, 0.5, -0.5])).collect();
+        {
+            let mut xtc_write = XDRFile::<access_mode::Write>::open(&test_file)?;
+            xtc_write.set_variable_natoms(true);
+            for k in 0..10 {
+                // Vary the number of atoms so frames have different sizes, including uncompressed ones
+                xtc_write.write_xtc(k, k as c_float * 2., matrix::new(), &x1[..5 * (k as usize + 1)], 1000.)?;
+            }
+        }
+
+        let mut xtc_read = XDRFile::<access_mode::Read>::open(&test_file)?;
+        assert_eq!(xtc_read.frame_count()?, 10);
+        for k in [7, 2, 9, 0, 1] {
+            xtc_read.seek_frame(k)?;
+            let frame = xtc_read.frames().next().unwrap()?;
+            assert_eq!(frame.step, k as c_int);
+            assert_eq!(frame.x.len(), 5 * (k + 1));
+        }
+        assert!(xtc_read.seek_frame(10).unwrap_err().is_eof());
+
+        // Streams other than files can be indexed too
+        let data = std::fs::read("tests/test_index.xtc").unwrap();
+        let mut xtc_cursor = XDRFile::<access_mode::Read>::from_reader(std::io::Cursor::new(data))?;
+        assert_eq!(xtc_cursor.index()?, &FrameIndex { mtime: (0, 0), ..xtc_read.index()?.clone() });
+        xtc_cursor.seek_frame(7)?;
+        assert_eq!(xtc_cursor.frames().next().unwrap()?.step, 7);
+
+        // Save the index, then check it is reloaded unchanged
+        let index = xtc_read.load_index(&sidecar)?.clone();
+        assert!(sidecar.exists());
+        assert_eq!(FrameIndex::load(&sidecar)?, index);
+        let mut xtc_read = XDRFile::<access_mode::Read>::open(&test_file)?;
+        assert_eq!(xtc_read.load_index(&sidecar)?, &index);
+
+        // Truncating the file invalidates the saved index, and drops the partial frame
+        let data = std::fs::read("tests/test_index.xtc").unwrap();
+        std::fs::write("tests/test_index.xtc", &data[..data.len() - 10]).unwrap();
+        let mut xtc_read = XDRFile::<access_mode::Read>::open(&test_file)?;
+        assert_eq!(xtc_read.load_index(&sidecar)?.len(), 9);
+        assert_eq!(FrameIndex::load(&sidecar)?.len(), 9);
+        Ok(())
+    }
+
+    #[test]
+    /// Test opening files from paths, including reporting OS errors
+    fn test_open_path() -> Result<(), Box<dyn std::error::Error>> {
+        use std::path::PathBuf;
+
+        let err = XDRFile::<access_mode::Read>::open_path("tests/does_not_exist.xtc").err().unwrap();
+        let ErrorKind::Io(e) = err.kind() else { panic!("Expected I/O error, got {:?}", err) };
+        assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+        assert!(XDRFile::<access_mode::Write>::open_path("tests").is_err());
+
+        // Paths don't need to be valid UTF-8
+        #[cfg(unix)]
+        let path: PathBuf = {
+            use std::os::unix::ffi::OsStrExt;
+            std::ffi::OsStr::from_bytes(b"tests/test_path_\xff.xtc").into()
+        };
+        #[cfg(not(unix))]
+        let path = PathBuf::from("tests/test_path_\u{e9}.xtc");
+
+        let x1 = vec![rvec([1., 2., 3.]); 20];
+        let mut xtc_write = XDRFile::<access_mode::Write>::open_path(&path)?;
+        xtc_write.write_xtc(0, 0., matrix::new(), &x1, 1000.)?;
+        xtc_write.close();
+        let mut xtc_append = XDRFile::<access_mode::Append>::open_path(&path)?;
+        xtc_append.write_xtc(1, 1., matrix::new(), &x1, 1000.)?;
+        xtc_append.close();
+        let mut xtc_read = XDRFile::<access_mode::Read>::open_path(&path)?;
+        assert_eq!(xtc_read.frame_count()?, 2);
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    /// Test that errors report the frame and byte offset where they happened
+    fn test_error_context() -> Result<(), Error> {
+        use std::error::Error as _;
+
+        let test_file = CString::new("tests/test_errors.xtc").unwrap();
+        let x1 = vec![rvec([1., 2., 3.]); 20];
+        {
+            let mut xtc_write = XDRFile::<access_mode::Write>::open(&test_file)?;
+            xtc_write.set_variable_natoms(true);
+            xtc_write.write_xtc(0, 0., matrix::new(), &x1, 1000.)?;
+            xtc_write.write_xtc(1, 1., matrix::new(), &x1[..15], 1000.)?;
+        }
+
+        let mut xtc_read = XDRFile::<access_mode::Read>::open(&test_file)?;
+        xtc_read.read_xtc(20)?;
+        let offset = xtc_read.handle.position();
+        let err = xtc_read.read_xtc(20).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::NatomsMismatch { expected: 20, found: 15 }));
+        assert_eq!(err.frame(), Some(1));
+        assert_eq!(err.offset(), Some(offset));
+        assert_eq!(err.to_string(), format!("expected 20 atoms, found 15 in frame 1 at byte offset {}", offset));
+        assert!(err.source().is_none());
+
+        let err = XDRFile::<access_mode::Read>::open(c"tests/does_not_exist.xtc").err().unwrap();
+        assert!(err.source().is_some());
+        // File names which aren't valid UTF-8 are passed on to the OS
+        #[cfg(unix)]
+        {
+            let err = XDRFile::<access_mode::Read>::open(c"tests/does_not_exist_\xff.xtc").err().unwrap();
+            assert!(matches!(err.kind(), ErrorKind::Io(e) if e.kind() == std::io::ErrorKind::NotFound));
+        }
+        assert_eq!(XDRStatus::exdrMAGIC.to_string(), "xdr error: Magic number");
+        Ok(())
+    }
+
+    #[test]
+    /// Test reading and writing in-memory streams, with and without seeking
+    fn test_xtc_streams() -> Result<(), Error> {
+        use std::io::Cursor;
+
+        let x1: Vec<_> = (0..30).map(|i| rvec([i as c_float * 0.2, 0.5, -0.5])).collect();
+        let mut xtc_write = XDRFile::<access_mode::Write>::from_writer(Cursor::new(Vec::new()))?;
+        xtc_write.set_variable_natoms(true);
+        for k in 0..5 {
+            xtc_write.write_xtc(k, k as c_float, matrix::new(), &x1[..5 * (k as usize + 1)], 1000.)?;
+        }
+        let data = xtc_write.into_inner::<Cursor<Vec<u8>>>()?.into_inner();
+
+        // Writing to an unseekable stream gives the same bytes
+        let mut xtc_write = XDRFile::<access_mode::Write>::from_unseekable_writer(Vec::new());
+        xtc_write.set_variable_natoms(true);
+        for k in 0..5 {
+            xtc_write.write_xtc(k, k as c_float, matrix::new(), &x1[..5 * (k as usize + 1)], 1000.)?;
+        }
+        assert!(xtc_write.into_inner::<Vec<u8>>()? == data);
+
+        let mut xtc_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data.clone()))?;
+        assert_eq!(xtc_read.read_xtc_natoms()?, 5);
+        assert_eq!(xtc_read.frame_count()?, 5);
+        let frames = xtc_read.frames().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(frames.len(), 5);
+
+        // Frames can be read in order from a pipe, but not peeked at
+        let mut xtc_read = XDRFile::<access_mode::Read>::from_unseekable_reader(Cursor::new(data));
+        assert!(matches!(xtc_read.read_xtc_natoms().unwrap_err().kind(), ErrorKind::Io(_)));
+        assert!(matches!(xtc_read.frame_count().unwrap_err().kind(), ErrorKind::Io(_)));
+        for (k, frame) in xtc_read.frames().enumerate() {
+            let frame = frame?;
+            assert_eq!(frame.step, k as c_int);
+            assert_eq!(frame, frames[k]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Test scanning frame headers without decompressing coordinates
+    fn test_xtc_headers() -> Result<(), Error> {
+        let test_file = CString::new("tests/test_headers.xtc").unwrap();
+        let x1: Vec<_> = (0..40).map(|i| rvec([i as c_float * 0.1, 0.5, -0.5])).collect();
+        let mut sim_box = matrix::new();
+        {
+            let mut xtc_write = XDRFile::<access_mode::Write>::open(&test_file)?;
+            xtc_write.set_variable_natoms(true);
+            for k in 0..8 {
+                sim_box.0[0][0] = k as c_float;
+                xtc_write.write_xtc(k, k as c_float * 0.5, sim_box, &x1[..5 * (k as usize + 1)], 100.)?;
+            }
+        }
+
+        let mut xtc_read = XDRFile::<access_mode::Read>::open(&test_file)?;
+        let headers = xtc_read.scan_headers()?;
+        assert_eq!(headers.len(), 8);
+        assert_eq!(headers.iter().map(|h| h.offset).collect::<Vec<_>>(), xtc_read.index()?.offsets);
+        for (k, header) in headers.iter().enumerate() {
+            assert_eq!(header.natoms, 5 * (k + 1));
+            assert_eq!(header.step, k as c_int);
+            assert_eq!(header.time, k as c_float * 0.5);
+            assert_eq!(header.sim_box.0[0][0], k as c_float);
+            if header.natoms <= 9 {
+                assert_eq!(header.prec, 0.);
+                assert_eq!(header.nbytes, header.natoms * DIM * 4);
+            } else {
+                assert_eq!(header.prec, 100.);
+            }
+        }
+
+        // Headers can be mixed with reading frames
+        assert_eq!(xtc_read.read_xtc_header()?, headers[0]);
+        let frame = xtc_read.read_xtc(10)?;
+        assert_eq!(frame.step, 1);
+        assert_eq!(xtc_read.headers().collect::<Result<Vec<_>, _>>()?, &headers[2..]);
+
+        // A truncated frame is an error when iterating, but is skipped by a scan
+        let data = std::fs::read("tests/test_headers.xtc").unwrap();
+        std::fs::write("tests/test_headers.xtc", &data[..data.len() - 8]).unwrap();
+        let mut xtc_read = XDRFile::<access_mode::Read>::open(&test_file)?;
+        assert_eq!(xtc_read.scan_headers()?, &headers[..7]);
+        let results: Vec<_> = xtc_read.headers().collect();
+        assert_eq!(results.len(), 8);
+        assert_eq!(results[7].as_ref().unwrap_err().frame(), Some(7));
+
+        // In-memory streams are scanned in the same way
+        let mut xtc_cursor = XDRFile::<access_mode::Read>::from_reader(std::io::Cursor::new(data[..data.len() - 8].to_vec()))?;
+        assert_eq!(xtc_cursor.scan_headers()?, &headers[..7]);
+        Ok(())
+    }
+
+    #[test]
+    /// Test seeking to a simulation time, including with times that go backwards
+    fn test_xtc_seek_time() -> Result<(), Error> {
+        let test_file = CString::new("tests/test_seek_time.xtc").unwrap();
+        let coords = |k: usize| (0..40)
+            .map(|i| rvec([(i * 7 + k) as c_float * 0.013, (i * i % 17) as c_float * 0.1, k as c_float * 0.01]))
+            .collect::<Vec<_>>();
+        {
+            let mut xtc_write = XDRFile::<access_mode::Write>::open(&test_file)?;
+            for k in 0..200 {
+                xtc_write.write_xtc(k as i32, k as c_float * 0.5, matrix::new(), &coords(k), 1000.)?;
+            }
+        }
+
+        let mut xtc_read = XDRFile::<access_mode::Read>::open(&test_file)?;
+        for (t, step) in [(-1., 0), (0., 0), (37.3, 75), (50., 100), (99.5, 199)] {
+            let header = xtc_read.seek_time(t)?;
+            assert_eq!(header.step, step, "Wrong frame for time {}", t);
+            let frame = xtc_read.read_xtc(40)?;
+            assert_eq!(frame.step, step, "Wrong frame read after seeking to time {}", t);
+            for (x, x1) in frame.x.iter().zip(coords(step as usize)) {
+                assert!((0..DIM).all(|j| f32::abs(x.0[j] - x1.0[j]) <= 1e-3));
+            }
+        }
+        xtc_read.seek_time(10.)?;
+        assert!(xtc_read.seek_time(100.).unwrap_err().is_eof());
+        assert_eq!(xtc_read.read_xtc(40)?.step, 20);
+
+        // Only a few headers are read, so a damaged frame away from the frames sampled doesn't
+        // get in the way, which it would if every header was read
+        let data = std::fs::read("tests/test_seek_time.xtc").unwrap();
+        let frame_size = data.len() / 200;
+        let mut damaged = data.clone();
+        damaged[3 * frame_size..3 * frame_size + 4].copy_from_slice(&[0; 4]);
+        let mut xtc_cursor = XDRFile::<access_mode::Read>::from_reader(std::io::Cursor::new(damaged))?;
+        assert_eq!(xtc_cursor.seek_time(75.)?.step, 150);
+        assert_eq!(xtc_cursor.read_xtc(40)?.step, 150);
+
+        // The index is bisected when it has been built, which also gives the frame number
+        let mut xtc_read = XDRFile::<access_mode::Read>::open(&test_file)?;
+        xtc_read.index()?;
+        assert_eq!(xtc_read.seek_time(37.3)?.step, 75);
+        assert_eq!(xtc_read.read_xtc(39).unwrap_err().frame(), Some(75));
+        assert!(xtc_read.seek_time(100.).unwrap_err().is_eof());
+
+        // Restarting part way through leaves times which go backwards
+        {
+            let mut xtc_write = XDRFile::<access_mode::Write>::open(&test_file)?;
+            for k in 0..200 {
+                let time = if k < 150 { k } else { k - 55 };
+                xtc_write.write_xtc(k as i32, time as c_float, matrix::new(), &coords(k), 1000.)?;
+            }
+        }
+        let mut xtc_read = XDRFile::<access_mode::Read>::open(&test_file)?;
+        assert_eq!(xtc_read.seek_time(5.)?.step, 5);
+        assert_eq!(xtc_read.seek_time(99.5)?.step, 100);
+        // Frames sampled either side of the restart are out of order, so the first frame in the
+        // file is found, rather than the one after the restart
+        assert_eq!(xtc_read.seek_time(120.)?.step, 120);
+        assert_eq!(xtc_read.read_xtc(40)?.step, 120);
+        xtc_read.index()?;
+        assert_eq!(xtc_read.seek_time(140.)?.step, 140);
+        assert_eq!(xtc_read.read_xtc(39).unwrap_err().frame(), Some(140));
+
+        // Seeking by time works on in-memory streams too
+        let data = std::fs::read("tests/test_seek_time.xtc").unwrap();
+        let mut xtc_cursor = XDRFile::<access_mode::Read>::from_reader(std::io::Cursor::new(data))?;
+        assert_eq!(xtc_cursor.seek_time(50.)?.step, 50);
+        Ok(())
+    }
+
+    #[test]
+    /// Test skipping damaged data and carrying on from the next frame which can be read
+    fn test_xtc_recover() -> Result<(), Error> {
+        use std::io::Cursor;
+
+        let coords = |k: usize| (0..30)
+            .map(|i| rvec([(i + k) as c_float * 0.1, (i % 4) as c_float * 0.2, k as c_float]))
+            .collect::<Vec<_>>();
+        let mut xtc_write = XDRFile::<access_mode::Write>::from_writer(Cursor::new(Vec::new()))?;
+        for k in 0..7 {
+            xtc_write.write_xtc(k as i32, k as c_float, matrix::new(), &coords(k), 1000.)?;
+        }
+        let mut data = xtc_write.into_inner::<Cursor<Vec<u8>>>()?.into_inner();
+        let mut xtc_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data.clone()))?;
+        let offsets = xtc_read.headers().map(|h| Ok(h?.offset as usize)).collect::<Result<Vec<_>, Error>>()?;
+
+        // Overwrite the header of frame 2, remove 3 bytes from frame 4 and truncate frame 6
+        data[offsets[2] + 4..offsets[2] + 40].fill(0x55);
+        data.drain(offsets[4] + 8..offsets[4] + 11);
+        data.truncate(offsets[6] - 3 + 20);
+        let len = data.len() as u64;
+
+        let mut xtc_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data.clone()))?;
+        let mut steps = Vec::new();
+        let mut skipped = Vec::new();
+        for item in xtc_read.recover_frames(None) {
+            match item? {
+                Recovered::Frame(frame) => {
+                    for (x, x1) in frame.x.iter().zip(coords(frame.step as usize)) {
+                        assert!((0..DIM).all(|j| f32::abs(x.0[j] - x1.0[j]) <= 1e-3));
+                    }
+                    steps.push(frame.step);
+                }
+                Recovered::Skipped(SkippedBytes { range, error }) => {
+                    assert_eq!(error.offset(), Some(range.start));
+                    skipped.push(range);
+                }
+            }
+        }
+        assert_eq!(steps, [0, 1, 3, 5]);
+        let offsets: Vec<_> = offsets.iter().map(|&offset| offset as u64).collect();
+        assert_eq!(skipped, [offsets[2]..offsets[3], offsets[4]..offsets[5] - 3, offsets[6] - 3..len]);
+
+        // Frames with the wrong number of atoms are skipped too
+        let mut xtc_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data.clone()))?;
+        let items = xtc_read.recover_frames(Some(20)).collect::<Result<Vec<_>, _>>()?;
+        assert!(matches!(&items[..], [Recovered::Skipped(SkippedBytes { range, .. })] if *range == (0..len)));
+        assert!(matches!(items[0], Recovered::Skipped(ref s) if matches!(s.error.kind(), ErrorKind::NatomsMismatch { expected: 20, found: 30 })));
+
+        // Streams which can't seek stop at the first damaged frame
+        let mut xtc_read = XDRFile::<access_mode::Read>::from_unseekable_reader(Cursor::new(data));
+        let mut items = xtc_read.recover_frames(None);
+        assert!(matches!(items.next(), Some(Ok(Recovered::Frame(_)))));
+        assert!(matches!(items.next(), Some(Ok(Recovered::Frame(_)))));
+        assert!(items.next().unwrap().is_err());
+        assert!(items.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    /// Test checking xtc and trr files for problems
+    fn test_check_trajectory() -> Result<(), Error> {
+        let xtc_path = "tests/test_check.xtc";
+        let x: Vec<_> = (0..30).map(|i| rvec([i as c_float * 0.1, (i % 4) as c_float * 0.2, 1.])).collect();
+        let mut offsets = Vec::new();
+        {
+            let mut xtc_write = XDRFile::<access_mode::Write>::open_path(xtc_path)?;
+            xtc_write.set_variable_natoms(true);
+            for k in 0..10 {
+                offsets.push(xtc_write.handle.position());
+                let time = if k < 6 { k } else { k + 2 };
+                let natoms = if k < 8 { 30 } else { 20 };
+                let mut sim_box = matrix::new();
+                sim_box.0[0][0] = if k < 5 { 3. } else { 3.5 };
+                let prec = if k == 0 { 100. } else { 1000. };
+                xtc_write.write_xtc(k, time as c_float, sim_box, &x[..natoms], prec)?;
+            }
+        }
+        let mut data = std::fs::read(xtc_path).unwrap();
+        let report = XDRFile::<access_mode::Read>::open_path(xtc_path)?.check_trajectory()?;
+        // The change in the number of atoms counts as a problem
+        assert!(!report.is_ok());
+        assert_eq!(report.format, TrajectoryFormat::Xtc);
+        assert_eq!((report.frames, report.natoms), (10, Some(30)));
+        assert_eq!((report.first_time, report.last_time, report.timestep), (Some(0.), Some(11.), Some(1.)));
+        assert_eq!(report.time_gaps, [TimeGap { frame: 6, time: 8., dt: 3. }]);
+        assert_eq!(report.natoms_changes, [NatomsChange { frame: 8, natoms: 20 }]);
+        assert_eq!(report.box_changes, 1);
+        assert_eq!(report.precisions, [100., 1000.]);
+
+        // Seekable in-memory streams can be checked as well as files
+        let mut xtc_cursor = XDRFile::<access_mode::Read>::from_reader(std::io::Cursor::new(data.clone()))?;
+        let cursor_report = xtc_cursor.check_trajectory()?;
+        assert_eq!((cursor_report.file_size, cursor_report.frames), (data.len() as u64, 10));
+
+        // Damage frame 3 and cut the last frame short
+        let offsets: Vec<_> = offsets.iter().map(|&offset| offset as usize).collect();
+        data[offsets[3] + 60..offsets[3] + 80].fill(0xaa);
+        data.truncate(data.len() - 10);
+        std::fs::write(xtc_path, &data).unwrap();
+        let mut xtc_read = XDRFile::<access_mode::Read>::open_path(xtc_path)?;
+        xtc_read.read_xtc(30)?;
+        let report = xtc_read.check_trajectory()?;
+        assert!(!report.is_ok());
+        assert_eq!(xtc_read.read_xtc(30)?.step, 1);
+        assert_eq!(report.frames, 8);
+        assert_eq!(report.truncated, Some(offsets[9] as u64));
+        assert_eq!(report.unreadable.len(), 1);
+        assert_eq!(report.unreadable[0].range, offsets[3] as u64..offsets[4] as u64);
+        assert_eq!(report.time_gaps, [TimeGap { frame: 3, time: 4., dt: 2. }, TimeGap { frame: 5, time: 8., dt: 3. }]);
+        let text = report.to_string();
+        assert!(text.contains("Frames: 8\n"), "{}", text);
+        assert!(text.contains(&format!("Truncated frame at byte offset {}", offsets[9])), "{}", text);
+
+        // trr files are checked in the same way
+        let trr_path = "tests/test_check.trr";
+        {
+            let mut trr_write = XDRFile::<access_mode::Write>::open_path(trr_path)?;
+            for k in 0..4 {
+                let mut sim_box = matrix::new();
+                sim_box.0[1][1] = 2. + k as c_float;
+                trr_write.write_trr(k, k as c_float * 0.5, 0., sim_box, Some(&x), None, Some(&x))?;
+            }
+        }
+        let report = XDRFile::<access_mode::Read>::open_path(trr_path)?.check_trajectory()?;
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.format, TrajectoryFormat::Trr);
+        assert_eq!((report.frames, report.natoms, report.timestep), (4, Some(30), Some(0.5)));
+        assert_eq!(report.box_changes, 3);
+        assert!(report.precisions.is_empty());
+
+        // Frames with blocks which can't be read are unreadable, both when checking and reading
+        let data = std::fs::read(trr_path).unwrap();
+        let frame_size = data.len() / 4;
+        let mut corrupt = data.clone();
+        // Energy block size of the second frame, after the magic number and version string
+        let e_size = frame_size + 4 + 4 + 4 + 12 + 4;
+        corrupt[e_size..e_size + 4].copy_from_slice(&8i32.to_be_bytes());
+        std::fs::write(trr_path, &corrupt).unwrap();
+        let report = XDRFile::<access_mode::Read>::open_path(trr_path)?.check_trajectory()?;
+        assert_eq!(report.frames, 3);
+        assert_eq!(report.unreadable[0].range, frame_size as u64..2 * frame_size as u64);
+        let mut trr_read = XDRFile::<access_mode::Read>::open_path(trr_path)?;
+        trr_read.read_trr(30)?;
+        assert_eq!(trr_read.read_trr(30).unwrap_err(), XDRStatus::exdrHEADER);
+
+        std::fs::write(trr_path, &data[..data.len() - 4]).unwrap();
+        let report = XDRFile::<access_mode::Read>::open_path(trr_path)?.check_trajectory()?;
+        assert_eq!((report.frames, report.truncated), (3, Some(3 * frame_size as u64)));
+        Ok(())
+    }
+
+    #[test]
+    /// Test following an xtc file while frames are written to it, including part way through
+    fn test_xtc_follow() -> Result<(), Error> {
+        use std::{io::{Cursor, Write}, thread, time::Duration};
+
+        let path = "tests/test_follow.xtc";
+        let coords = |k: usize| (0..20)
+            .map(|i| rvec([(i + k) as c_float * 0.1, (i % 3) as c_float, 0.5]))
+            .collect::<Vec<_>>();
+        let mut xtc_write = XDRFile::<access_mode::Write>::from_writer(Cursor::new(Vec::new()))?;
+        let mut offsets = vec![0];
+        for k in 0..6 {
+            xtc_write.write_xtc(k as i32, k as c_float, matrix::new(), &coords(k), 1000.)?;
+            offsets.push(xtc_write.handle.position() as usize);
+        }
+        let data = xtc_write.into_inner::<Cursor<Vec<u8>>>()?.into_inner();
+
+        // Start with two frames and part of the third
+        std::fs::write(path, &data[..offsets[2] + 30]).unwrap();
+        let writer = {
+            let data = data.clone();
+            // Write the rest in uneven pieces, some of which end part way through a header
+            let ends = [offsets[3] + 6, offsets[4], offsets[5] - 50, data.len()];
+            thread::spawn(move || {
+                let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+                for end in ends {
+                    thread::sleep(Duration::from_millis(20));
+                    let start = file.metadata().unwrap().len() as usize;
+                    file.write_all(&data[start..end]).unwrap();
+                    file.flush().unwrap();
+                }
+            })
+        };
+
+        let mut xtc_read = XDRFile::<access_mode::Read>::open_path(path)?;
+        let steps = xtc_read.follow(Duration::from_millis(2), Some(Duration::from_millis(200)))
+            .map(|frame| frame.map(|frame| frame.step))
+            .collect::<Result<Vec<_>, _>>()?;
+        writer.join().unwrap();
+        assert_eq!(steps, [0, 1, 2, 3, 4, 5]);
+
+        // Data which is already complete is still checked
+        let mut bad = data.clone();
+        bad[offsets[1]..offsets[1] + 4].fill(0);
+        std::fs::write(path, &bad).unwrap();
+        let mut xtc_read = XDRFile::<access_mode::Read>::open_path(path)?;
+        let mut frames = xtc_read.follow(Duration::from_millis(2), None);
+        assert_eq!(frames.next().unwrap()?.step, 0);
+        assert_eq!(frames.next().unwrap().unwrap_err().offset(), Some(offsets[1] as u64));
+        assert!(frames.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    /// Test carrying on writing an xtc file after a crash, from the last complete frame or a
+    /// checkpoint step
+    fn test_xtc_resume() -> Result<(), Error> {
+        let path = "tests/test_resume.xtc";
+        let x: Vec<_> = (0..25).map(|i| rvec([i as c_float * 0.1, 0.2, -0.3])).collect();
+        let steps = |path| -> Result<Vec<c_int>, Error> {
+            let mut xtc_read = XDRFile::<access_mode::Read>::open_path(path)?;
+            xtc_read.frames().map(|frame| Ok(frame?.step)).collect()
+        };
+        {
+            let mut xtc_write = XDRFile::<access_mode::Write>::open_path(path)?;
+            xtc_write.set_xtc_2023(true);
+            for k in 0..6 {
+                xtc_write.write_xtc(k * 10, k as c_float, matrix::new(), &x, 1000.)?;
+            }
+        }
+        // Crash part way through writing a frame
+        let data = std::fs::read(path).unwrap();
+        let frame_size = data.len() / 6;
+        let mut crashed = data.clone();
+        crashed.extend_from_slice(&data[..frame_size / 2]);
+        std::fs::write(path, &crashed).unwrap();
+        assert!(XDRFile::<access_mode::Read>::open_path(path)?.frames().nth(6).unwrap().is_err());
+
+        let (mut xtc_append, info) = XDRFile::<access_mode::Append>::open_resume(path, None)?;
+        assert_eq!(info, ResumeInfo { frames: 6, frames_removed: 0, bytes_removed: frame_size as u64 / 2 });
+        xtc_append.write_xtc(60, 6., matrix::new(), &x, 1000.)?;
+        xtc_append.close();
+        assert_eq!(steps(path)?, [0, 10, 20, 30, 40, 50, 60]);
+        // The magic number of the existing frames is kept
+        let data = std::fs::read(path).unwrap();
+        assert_eq!(data.len(), 7 * frame_size);
+        assert_eq!(data[6 * frame_size..6 * frame_size + 4], crate::xtc::MAGIC_2023.to_be_bytes());
+
+        // Restart from a checkpoint at step 25, dropping the frames written after it
+        let (mut xtc_append, info) = XDRFile::<access_mode::Append>::open_resume(path, Some(25))?;
+        assert_eq!(info, ResumeInfo { frames: 3, frames_removed: 4, bytes_removed: 4 * frame_size as u64 });
+        xtc_append.write_xtc(30, 3., matrix::new(), &x, 1000.)?;
+        xtc_append.close();
+        assert_eq!(steps(path)?, [0, 10, 20, 30]);
+
+        // Damage before the end of the file is an error, and the file is left alone
+        let mut damaged = std::fs::read(path).unwrap();
+        damaged[2 * frame_size..2 * frame_size + 4].copy_from_slice(&[0; 4]);
+        damaged.extend_from_slice(&data[..frame_size / 2]);
+        std::fs::write(path, &damaged).unwrap();
+        let err = XDRFile::<access_mode::Append>::open_resume(path, None).err().unwrap();
+        assert_eq!(err, XDRStatus::exdrMAGIC);
+        assert_eq!(err.offset(), Some(2 * frame_size as u64));
+        assert_eq!(std::fs::read(path).unwrap(), damaged);
+
+        // As is a file in another format
+        let trr_path = "tests/test_resume.trr";
+        {
+            let mut trr_write = XDRFile::<access_mode::Write>::open_path(trr_path)?;
+            trr_write.write_trr(0, 0., 0., matrix::new(), Some(&x), None, None)?;
+        }
+        let trr = std::fs::read(trr_path).unwrap();
+        assert!(XDRFile::<access_mode::Append>::open_resume(trr_path, None).is_err());
+        assert_eq!(std::fs::read(trr_path).unwrap(), trr);
+        Ok(())
+    }
+
+    #[test]
+    /// Test that frames with a different number of atoms are rejected unless allowed
+    fn test_natoms_consistency() -> Result<(), Error> {
+        let x = vec![rvec([0.1, 0.2, 0.3]); 12];
+        let path = "tests/test_natoms.xtc";
+        {
+            let mut xtc_write = XDRFile::<access_mode::Write>::open_path(path)?;
+            xtc_write.write_xtc(0, 0., matrix::new(), &x, 1000.)?;
+            let err = xtc_write.write_xtc(1, 1., matrix::new(), &x[..11], 1000.).unwrap_err();
+            assert!(matches!(err.kind(), ErrorKind::NatomsMismatch { expected: 12, found: 11 }), "{}", err);
+            assert_eq!(err.frame(), Some(1));
+            let dx = vec![dvec([0.1, 0.2, 0.3]); 13];
+            assert!(xtc_write.write_xtc_f64(1, 1., matrix::new(), &dx, 1000.).is_err());
+            xtc_write.write_xtc(1, 1., matrix::new(), &x, 1000.)?;
+        }
+        // Rejected frames aren't written
+        let mut xtc_read = XDRFile::<access_mode::Read>::open_path(path)?;
+        assert_eq!(xtc_read.frames().map(|frame| Ok(frame?.step)).collect::<Result<Vec<_>, Error>>()?, [0, 1]);
+
+        // The number of atoms is read from the existing file when appending
+        let mut xtc_append = XDRFile::<access_mode::Append>::open_path(path)?;
+        assert!(xtc_append.write_xtc(2, 2., matrix::new(), &x[..4], 1000.).is_err());
+        xtc_append.set_variable_natoms(true);
+        xtc_append.write_xtc(2, 2., matrix::new(), &x[..4], 1000.)?;
+        xtc_append.close();
+        let (mut xtc_append, _) = XDRFile::<access_mode::Append>::open_resume(path, None)?;
+        assert!(xtc_append.write_xtc(3, 3., matrix::new(), &x[..4], 1000.).is_err());
+        xtc_append.close();
+
+        // The same applies to trr files, except for frames without any atoms
+        let path = "tests/test_natoms.trr";
+        {
+            let mut trr_write = XDRFile::<access_mode::Write>::open_path(path)?;
+            trr_write.write_trr(0, 0., 0., matrix::new(), Some(&x), None, None)?;
+            trr_write.write_trr(1, 1., 0., matrix::new(), None, None, None)?;
+        }
+        let mut trr_append = XDRFile::<access_mode::Append>::open_path(path)?;
+        let err = trr_append.write_trr(2, 2., 0., matrix::new(), None, Some(&x[..3]), None).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::NatomsMismatch { expected: 12, found: 3 }), "{}", err);
+        trr_append.write_trr(2, 2., 0., matrix::new(), None, Some(&x), None)?;
+        Ok(())
+    }
+
+    #[test]
+    /// Test reading a subset of the atoms in each frame
+    fn test_xtc_subset() -> Result<(), Error> {
+        use std::io::Cursor;
+
+        let offsets = [[0., 0., 0.], [0.0957, 0., 0.], [-0.024, 0.0927, 0.]];
+        let x1: Vec<_> = (0..600)
+            .map(|i| {
+                let o = offsets[i % 3];
+                rvec([(i / 3 % 10) as c_float * 0.31 + o[0], (i / 30) as c_float * 0.29 + o[1], o[2]])
+            })
+            .collect();
+        let mut xtc_write = XDRFile::<access_mode::Write>::from_writer(Cursor::new(Vec::new()))?;
+        xtc_write.set_variable_natoms(true);
+        for k in 0..3 {
+            xtc_write.write_xtc(k, k as c_float, matrix::new(), &x1, 1000.)?;
+        }
+        xtc_write.write_xtc(3, 3., matrix::new(), &x1[..6], 1000.)?;
+        let data = xtc_write.into_inner::<Cursor<Vec<u8>>>()?.into_inner();
+
+        let selections = [
+            Selection::from(100..300),
+            Selection::from(vec![599, 4, 4, 0, 301]),
+            Selection::from_iter((0..600).step_by(7)),
+        ];
+        let mut full = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data.clone()))?;
+        let mut subset = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data))?;
+        let mut frame = XTCFrame::empty();
+        for selection in &selections {
+            let expected = full.read_xtc(600)?;
+            subset.read_xtc_subset_reuse(600, selection, &mut frame)?;
+            assert_eq!(frame.step, expected.step);
+            assert_eq!(frame.prec, expected.prec);
+            assert_eq!(frame.x.len(), selection.len());
+            for (x, &i) in frame.x.iter().zip(selection.atoms()) {
+                assert_eq!(*x, expected.x[i]);
+            }
+        }
+
+        // Atoms out of range are rejected without reading the frame
+        let err = subset.read_xtc_subset(6, &Selection::from(vec![2, 6])).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::AtomIndex { index: 6, natoms: 6 }));
+        let frame = subset.read_xtc_subset(6, &Selection::from(vec![5, 1]))?;
+        assert_eq!(frame.step, 3);
+        assert_eq!(frame.x, [x1[5], x1[1]]);
+        Ok(())
+    }
+
+    #[test]
+    /// Test reading and writing GROMACS index files, and using the groups to select atoms
+    fn test_ndx() -> Result<(), Error> {
+        use std::io::Cursor;
+
+        let text = "[ System ]\n   1    2    3\n4 5\n\n[  Protein Backbone ]\n  7 2\n[ Empty ]\n";
+        let ndx = IndexFile::from_reader(text.as_bytes())?;
+        assert_eq!(ndx.len(), 3);
+        assert_eq!(ndx.get("System").unwrap().atoms, [0, 1, 2, 3, 4]);
+        assert_eq!(ndx.get("Protein Backbone").unwrap().atoms, [6, 1]);
+        assert!(ndx.get("Empty").unwrap().atoms.is_empty());
+        assert!(ndx.get("Water").is_none());
+
+        // Written in the same layout as GROMACS
+        let mut ndx = IndexFile::new();
+        ndx.push(IndexGroup::new("Long", (0..20).collect()));
+        ndx.push(IndexGroup::new("Short", vec![1233, 4]));
+        let mut written = Vec::new();
+        ndx.to_writer(&mut written)?;
+        let expected = "[ Long ]\n   1    2    3    4    5    6    7    8    9   10   11   12   13   14   15\n  16   17   18   19   20\n[ Short ]\n1234    5\n";
+        assert_eq!(String::from_utf8(written.clone()).unwrap(), expected);
+        assert_eq!(IndexFile::from_reader(&written[..])?, ndx);
+
+        for (text, line) in [("1 2\n", 1), ("[ A ]\n1 0\n", 2), ("[ A ]\n1\n[ B\n", 3), ("[ A ]\n1 x\n", 2)] {
+            let err = IndexFile::from_reader(text.as_bytes()).unwrap_err();
+            assert!(matches!(err.kind(), ErrorKind::Parse { line: l, .. } if *l == line), "{}", err);
+        }
+
+        // Groups select atoms when reading and writing frames
+        let x1: Vec<_> = (0..30).map(|i| rvec([i as c_float, 0., 0.])).collect();
+        let group = IndexGroup::new("Some", vec![25, 3, 11, 12, 13, 14, 15, 16, 17, 18, 19]);
+        let mut xtc_write = XDRFile::<access_mode::Write>::from_writer(Cursor::new(Vec::new()))?;
+        xtc_write.set_variable_natoms(true);
+        xtc_write.write_xtc(0, 0., matrix::new(), &x1, 1000.)?;
+        xtc_write.write_xtc_subset(1, 1., matrix::new(), &x1, &group.selection(), 1000.)?;
+        let err = xtc_write.write_xtc_subset(2, 2., matrix::new(), &x1[..20], &group.selection(), 1000.).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::AtomIndex { index: 25, natoms: 20 }));
+        let data = xtc_write.into_inner::<Cursor<Vec<u8>>>()?.into_inner();
+
+        let mut xtc_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data))?;
+        let subset = xtc_read.read_xtc_subset(30, &(&group).into())?;
+        for (x, &i) in subset.x.iter().zip(&group.atoms) {
+            assert!(f32::abs(x.0[0] - x1[i].0[0]) <= 1e-3);
+        }
+        assert_eq!(xtc_read.read_xtc(group.atoms.len())?.x, subset.x);
+        Ok(())
+    }
+
+    #[test]
+    /// Test reading and writing .gro files
+    fn test_gro() -> Result<(), Error> {
+        use super::gro::{box_from_gro, box_to_gro};
+
+        let text = "\
+Two waters t=  12.50000 step= 25
+    6
+    1SOL     OW    1   0.126   1.624   1.679  0.1227 -0.0580  0.0434
+    1SOL    HW1    2   0.190   1.661   1.747  0.8085  0.3191 -0.7791
+    1SOL    HW2    3   0.177   1.568   1.613 -0.9045 -2.6469  1.3180
+99999SOL     OW99999   1.275   0.053   0.622  0.2519  0.3140 -0.1734
+    0SOL    HW1    0   1.337   0.002   0.680 -1.0641 -1.1349  0.0257
+    0SOL    HW2    1   1.326   0.120   0.568  1.9427 -0.8216 -0.0244
+   1.86206   1.86206   1.86206
+";
+        let gro = GroFile::from_reader(text.as_bytes())?;
+        assert_eq!(gro.atoms.len(), 6);
+        assert_eq!(gro.atoms[1], GroAtom { res_number: 1, res_name: "SOL".into(), name: "HW1".into(), number: 2 });
+        assert_eq!(gro.atoms[3].number, 99999);
+        assert_eq!(gro.x[3], rvec([1.275, 0.053, 0.622]));
+        assert_eq!(gro.v.as_ref().unwrap()[2], rvec([-0.9045, -2.6469, 1.3180]));
+        assert_eq!(gro.sim_box, box_from_gro([1.86206, 1.86206, 1.86206, 0., 0., 0., 0., 0., 0.]));
+        assert_eq!(gro.precision, 3);
+
+        // Writing gives back the same text
+        let mut written = Vec::new();
+        gro.to_writer(&mut written)?;
+        assert_eq!(String::from_utf8(written).unwrap(), text);
+
+        let frame = gro.to_xtc_frame();
+        assert_eq!(frame.step, 25);
+        assert_eq!(frame.time, 12.5);
+        assert_eq!(frame.x, gro.x);
+
+        // Triclinic box, higher precision and no velocities
+        let text = "Box\n    1\n    1ALA      N    1   1.23456   2.34567   3.45678\n   5.00000   5.00000   3.53553   0.00000   0.00000   0.00000   0.00000   2.50000   2.50000\n";
+        let gro = GroFile::from_reader(text.as_bytes())?;
+        assert_eq!(gro.x[0], rvec([1.23456, 2.34567, 3.45678]));
+        assert!(gro.v.is_none());
+        assert_eq!(gro.sim_box.0[2], [2.5, 2.5, 3.53553]);
+        assert_eq!(box_to_gro(&gro.sim_box), [5., 5., 3.53553, 0., 0., 0., 0., 2.5, 2.5]);
+        assert_eq!(gro.precision, 5);
+        // The precision is kept when writing
+        let mut written = Vec::new();
+        gro.to_writer(&mut written)?;
+        assert_eq!(String::from_utf8(written).unwrap(), text);
+
+        for (text, line) in [("Title\n", 2), ("Title\n2\n", 3), ("T\n1\n    1ALA      N    1   1.000   x.000   3.000\n1 1 1\n", 3), ("T\n0\n1 1\n", 3), ("T\n99999999999999\n", 3)] {
+            let err = GroFile::from_reader(text.as_bytes()).unwrap_err();
+            assert!(matches!(err.kind(), ErrorKind::Parse { line: l, .. } if *l == line), "{}", err);
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Test reading and writing PDB files, and converting models to xtc frames
+    fn test_pdb() -> Result<(), Error> {
+        use std::io::Cursor;
+        use super::pdb::{box_from_cryst1, box_to_cryst1};
+
+        let text = "\
+TITLE     Two frames t=   0.00000 step= 0
+REMARK    THIS IS A SIMULATION BOX
+CRYST1   50.000   60.000   70.000  90.00  90.00  90.00 P 1           1
+MODEL        1
+ATOM      1  N   ALA A   1      11.104   6.134  -6.504  1.00  0.00           N
+ATOM      2  CA  ALA A   1      11.639   6.071  -5.147  1.00  0.00           C
+HETATM    3  O   HOH B   2       1.000   2.000   3.000
+TER
+ENDMDL
+MODEL        2
+ATOM      1  N   ALA A   1      12.104   7.134  -7.504  1.00  0.00           N
+ATOM      2  CA  ALA A   1      12.639   7.071  -6.147  1.00  0.00           C
+HETATM    3  O   HOH B   2       2.000   3.000   4.000
+TER
+ENDMDL
+END
+";
+        let pdb = PdbFile::from_reader(text.as_bytes())?;
+        assert_eq!(pdb.models.len(), 2);
+        let model = &pdb.models[0];
+        assert_eq!(model.number, 1);
+        assert_eq!(model.atoms.len(), 3);
+        assert_eq!(model.atoms[1].name, "CA");
+        assert_eq!(model.atoms[1].res_name, "ALA");
+        assert_eq!(model.atoms[1].chain_id, 'A');
+        assert_eq!(model.atoms[1].element, "C");
+        assert!(model.atoms[2].hetatm);
+        assert_eq!(model.atoms[2].occupancy, 1.);
+        assert_eq!(model.x[2], rvec([0.1, 0.2, 0.3]));
+        assert_eq!(model.sim_box.unwrap().0, [[5., 0., 0.], [0., 6., 0.], [0., 0., 7.]]);
+        assert_eq!(pdb.models[1].number, 2);
+
+        // Writing and reading again gives the same models
+        let mut written = Vec::new();
+        pdb.to_writer(&mut written)?;
+        let text = String::from_utf8(written).unwrap();
+        assert!(text.contains("\nATOM      2  CA  ALA A   1      11.639   6.071  -5.147  1.00  0.00           C  \n"), "{}", text);
+        assert_eq!(PdbFile::from_reader(text.as_bytes())?.models.len(), 2);
+        assert_eq!(PdbFile::from_reader(text.as_bytes())?.models[1].atoms, pdb.models[1].atoms);
+
+        // Models can be streamed as a trajectory and written to xtc
+        let mut xtc_write = XDRFile::<access_mode::Write>::from_writer(Cursor::new(Vec::new()))?;
+        for frame in PdbReader::new(text.as_bytes()).xtc_frames() {
+            let frame = frame?;
+            xtc_write.write_xtc(frame.step, frame.time, frame.sim_box, &frame.x, frame.prec)?;
+        }
+        let data = xtc_write.into_inner::<Cursor<Vec<u8>>>()?.into_inner();
+        let mut xtc_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data))?;
+        let frames = xtc_read.frames().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(frames.len(), 2);
+        // The second model has the title of the first
+        assert_eq!(pdb.models[1].title, pdb.models[0].title);
+        assert_eq!((frames[0].step, frames[1].step), (0, 0));
+        assert_eq!(frames[1].x[2], rvec([0.2, 0.3, 0.4]));
+        assert_eq!(frames[1].sim_box.0[2][2], 7.);
+
+        // Step and time come from the most recent title
+        let text = "\
+TITLE     Protein t=   1.00000 step= 10
+MODEL        1
+ATOM      1  N   ALA A   1      11.104   6.134  -6.504  1.00  0.00           N
+ENDMDL
+MODEL        2
+ATOM      1  N   ALA A   1      12.104   7.134  -7.504  1.00  0.00           N
+ENDMDL
+TITLE     Protein t=   3.00000 step= 30
+MODEL        3
+ATOM      1  N   ALA A   1      13.104   8.134  -8.504  1.00  0.00           N
+ENDMDL
+";
+        let step_time = PdbReader::new(text.as_bytes()).xtc_frames()
+            .map(|frame| frame.map(|frame| (frame.step, frame.time)))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(step_time, [(10, 1.), (10, 1.), (30, 3.)]);
+
+        // Triclinic boxes
+        let m = box_from_cryst1(7.0, 7.0, 7.0, 60., 60., 90.);
+        assert!(f32::abs(m.0[2][0] - 3.5) < 1e-5 && f32::abs(m.0[2][1] - 3.5) < 1e-5);
+        assert!(f32::abs(m.0[2][2] - 4.9497476) < 1e-5);
+        let cryst1 = box_to_cryst1(&m);
+        for (v, expected) in cryst1.iter().zip([7., 7., 7., 60., 60., 90.]) {
+            assert!(f32::abs(v - expected) < 1e-4, "{:?}", cryst1);
+        }
+
+        let err = PdbFile::from_reader("ATOM      1  N   ALA A   1      11.104   x.134  -6.504\n".as_bytes()).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Parse { line: 1, .. }), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    /// Test reading and writing frames with the GROMACS 2023 magic number
+    fn test_xtc_2023() -> Result<(), Box<dyn std::error::Error>> {
+        use std::io::Cursor;
+        use crate::{xdr::XDRFILE, xtc::{self, xtc_header}};
+
+        let x: Vec<_> = (0..50).map(|i| rvec([i as f32 * 0.1, (i % 7) as f32, -0.25 * i as f32])).collect();
+        let write = |force: bool| -> Result<Vec<u8>, Error> {
+            let mut xtc_write = XDRFile::<access_mode::Write>::from_writer(Cursor::new(Vec::new()))?;
+            xtc_write.set_variable_natoms(true);
+            xtc_write.set_xtc_2023(force);
+            xtc_write.write_xtc(0, 0., matrix::new(), &x, 1000.)?;
+            xtc_write.write_xtc(1, 1., matrix::new(), &x[..5], 1000.)?;
+            xtc_write.write_xtc(2, 2., matrix::new(), &x[5..], 1000.)?;
+            Ok(xtc_write.into_inner::<Cursor<Vec<u8>>>()?.into_inner())
+        };
+        let classic = write(false)?;
+        let data = write(true)?;
+        assert_eq!(classic[..4], xtc::MAGIC.to_be_bytes());
+        assert_eq!(data[..4], xtc::MAGIC_2023.to_be_bytes());
+        // Only the byte count of compressed frames is larger
+        assert_eq!(data.len(), classic.len() + 8);
+
+        let mut xtc_classic = XDRFile::<access_mode::Read>::from_reader(Cursor::new(classic))?;
+        let mut xtc_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data.clone()))?;
+        let frames = xtc_read.frames().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(frames.len(), 3);
+        for (frame, expected) in frames.iter().zip(xtc_classic.frames()) {
+            assert_eq!(*frame, expected?);
+        }
+
+        // Frames can be found by their headers and indexed
+        let path = "tests/test_xtc_2023.xtc";
+        std::fs::write(path, &data)?;
+        let mut xtc_read = XDRFile::<access_mode::Read>::open_path(path)?;
+        let headers = xtc_read.scan_headers()?;
+        assert_eq!(headers.iter().map(|h| h.natoms).collect::<Vec<_>>(), [50, 5, 45]);
+        assert_eq!(headers[2].offset, headers[1].offset + 16 + 36 + 4 + 60);
+        assert_eq!(xtc_read.seek_time(1.5)?.step, 2);
+        xtc_read.seek_frame(2)?;
+        assert_eq!(xtc_read.read_xtc(45)?, frames[2]);
+
+        // A corrupt byte count larger than any frame of 50 atoms is rejected without allocating it
+        let mut corrupt = data.clone();
+        corrupt[88..96].copy_from_slice(&u64::MAX.to_be_bytes());
+        let mut xtc_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(corrupt))?;
+        assert!(matches!(xtc_read.read_xtc(50).unwrap_err().kind(), ErrorKind::Status(XDRStatus::exdr3DX)));
+
+        // The writer switches automatically for very large frames
+        let mut xd = XDRFILE::from_writer(Cursor::new(Vec::new()))?;
+        let (mut natoms, mut step, mut time) = (xtc::MAX_NATOMS_1995 + 1, 0, 0.);
+        assert_eq!(xtc_header(&mut xd, &mut natoms, &mut step, &mut time, false), XDRStatus::exdrOK);
+        assert_eq!(xd.into_inner::<Cursor<Vec<u8>>>()?.into_inner()[..4], xtc::MAGIC_2023.to_be_bytes());
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs a 5 GiB sparse file, which takes up real space where sparse files aren't supported"]
+    /// Test indexing and seeking to frames beyond 4 GB, using a sparse file
+    fn test_xtc_large_offsets() -> Result<(), Box<dyn std::error::Error>> {
+        use std::io::{SeekFrom, Write};
+        use crate::xtc;
+
+        /// Temporary directory which is removed even if the test fails
+        struct TempDir(std::path::PathBuf);
+        impl Drop for TempDir {
+            fn drop(&mut self) {
+                let _ = std::fs::remove_dir_all(&self.0);
+            }
+        }
+
+        let dir = TempDir(std::env::temp_dir().join(format!("xdrfile_large_offsets_{}", std::process::id())));
+        std::fs::create_dir_all(&dir.0)?;
+        let path = &dir.0.join("test_large_offsets.xtc");
+        let sidecar = FrameIndex::sidecar_path(path);
+        let x: Vec<_> = (0..20).map(|i| rvec([i as f32 * 0.1, (i % 3) as f32, -0.5])).collect();
+        {
+            let mut xtc_write = XDRFile::<access_mode::Write>::open_path(path)?;
+            xtc_write.write_xtc(0, 0., matrix::new(), &x, 1000.)?;
+        }
+
+        // Frame whose coordinate data is a 5 GiB hole, so the frames after it start past 4 GB
+        // without being stored on disk
+        let hole: u64 = 5 << 30;
+        let mut header = Vec::new();
+        for value in [xtc::MAGIC_2023, 20, 1] {
+            header.extend(value.to_be_bytes());
+        }
+        header.extend(1f32.to_be_bytes());
+        header.extend([0; 9 * 4]);
+        header.extend(20i32.to_be_bytes());
+        header.extend(1000f32.to_be_bytes());
+        header.extend([0; 7 * 4]);
+        header.extend(hole.to_be_bytes());
+        {
+            let mut file = std::fs::OpenOptions::new().append(true).open(path)?;
+            file.write_all(&header)?;
+            file.set_len(file.metadata()?.len() + hole)?;
+        }
+        {
+            let mut xtc_append = XDRFile::<access_mode::Append>::open_path(path)?;
+            xtc_append.set_variable_natoms(true);
+            for k in 2..5 {
+                xtc_append.write_xtc(k, k as f32, matrix::new(), &x[..5 * k as usize], 1000.)?;
+            }
+        }
+
+        let mut xtc_read = XDRFile::<access_mode::Read>::open_path(path)?;
+        let headers = xtc_read.scan_headers()?;
+        assert_eq!(headers.iter().map(|h| h.step).collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+        assert_eq!(headers[1].nbytes as u64, hole);
+        assert_eq!(headers[2].offset, headers[1].offset + header.len() as u64 + hole);
+        assert!(headers[2].offset > 1 << 32);
+
+        let index = xtc_read.index()?.clone();
+        assert_eq!(index.offsets, headers.iter().map(|h| h.offset).collect::<Vec<_>>());
+        for k in [3, 0, 4, 2] {
+            xtc_read.seek_frame(k)?;
+            let frame = xtc_read.read_xtc(if k == 0 { 20 } else { 5 * k })?;
+            assert_eq!(frame.step, k as c_int);
+            for (x, x1) in frame.x.iter().zip(&x) {
+                assert!((0..DIM).all(|j| f32::abs(x.0[j] - x1.0[j]) <= 1e-3));
+            }
+        }
+        // Reading the number of atoms returns to the same large offset
+        xtc_read.seek_frame(3)?;
+        assert_eq!(xtc_read.read_xtc_natoms()?, 20);
+        assert_eq!(xtc_read.read_xtc(15)?.step, 3);
+
+        // Large offsets survive saving and loading the index
+        index.save(&sidecar)?;
+        assert_eq!(FrameIndex::load(&sidecar)?, index);
+
+        // Seeking with the low-level API uses 64-bit offsets too
+        let mut xd = crate::xdr::XDRFILE::open(path, b'r' as _)?;
+        assert_eq!(xd.seek(SeekFrom::Start(headers[4].offset)), XDRStatus::exdrOK);
+        assert_eq!(xd.position(), headers[4].offset);
+        assert_eq!(xd.tell(), headers[4].offset as i64);
+        let mut magic = [0];
+        assert_eq!(xd.read_int(&mut magic), 1);
+        assert_eq!(magic[0], xtc::MAGIC);
+        Ok(())
+    }
+
+    #[test]
+    /// Test writing and reading double precision coordinates
+    fn test_xtc_f64() -> Result<(), Error> {
+        use std::io::Cursor;
+
+        let x1: Vec<_> = (0..100)
+            .map(|i| dvec([i as f64 * 0.0123456789, 1. / (i + 1) as f64, -(i as f64).sqrt()]))
+            .collect();
+        let mut xtc_write = XDRFile::<access_mode::Write>::from_writer(Cursor::new(Vec::new()))?;
+        xtc_write.set_variable_natoms(true);
+        xtc_write.write_xtc_f64(0, 0., matrix::new(), &x1, 10000.)?;
+        xtc_write.write_xtc_f64(1, 1., matrix::new(), &x1[..4], 10000.)?;
+        let data = xtc_write.into_inner::<Cursor<Vec<u8>>>()?.into_inner();
+
+        let mut xtc_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data.clone()))?;
+        let frame = xtc_read.read_xtc_f64(100)?;
+        assert_eq!(frame.prec, 10000.);
+        for (x, x2) in x1.iter().zip(&frame.x) {
+            for j in 0..DIM {
+                assert!(f64::abs(x.0[j] - x2.0[j]) <= 0.5e-4 + 1e-9);
+            }
+        }
+        let frame = xtc_read.read_xtc_f64(4)?;
+        assert_eq!(frame.step, 1);
+        for (x, x2) in x1.iter().zip(&frame.x) {
+            assert_eq!(x.0.map(|v| v as f32 as f64), x2.0);
+        }
+
+        // The same file can be read in single precision
+        let mut xtc_read_f64 = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data.clone()))?;
+        let mut xtc_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data))?;
+        let frame = xtc_read.read_xtc(100)?;
+        let frame_f64 = xtc_read_f64.read_xtc_f64(100)?;
+        for (x, x2) in frame.x.iter().zip(&frame_f64.x) {
+            for j in 0..DIM {
+                assert!(f64::abs(x.0[j] as f64 - x2.0[j]) <= 1e-6);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Test the checked primitive read/write functions
+    fn test_primitives() -> Result<(), Error> {
+        use std::io::Cursor;
+
+        let mut xdr_write = XDRFile::<access_mode::Write>::from_writer(Cursor::new(Vec::new()))?;
+        xdr_write.write_int(&[-1, 2, 3])?;
+        xdr_write.write_uint(&[u32::MAX])?;
+        xdr_write.write_short(&[-7])?;
+        xdr_write.write_ushort(&[65535])?;
+        xdr_write.write_char(&[b'x' as c_char])?;
+        xdr_write.write_uchar(&[200])?;
+        xdr_write.write_float(&[1.5, -2.25])?;
+        xdr_write.write_double(&[std::f64::consts::PI])?;
+        xdr_write.write_string("sidecar", 16)?;
+        xdr_write.write_opaque(&[1, 2, 3, 4, 5])?;
+        assert!(xdr_write.write_string("too long", 4).is_err());
+        xdr_write.write_string("too long", 8)?;
+        xdr_write.write_uint(&[5])?;
+        xdr_write.write_opaque(&[0xff, 0xfe, 0, 0, 0])?;
+        let data = xdr_write.into_inner::<Cursor<Vec<u8>>>()?.into_inner();
+        // Everything is stored in multiples of 4 bytes
+        assert_eq!(data.len(), 4 * (3 + 1 + 1 + 1 + 1 + 1 + 2 + 2 + 1 + 2 + 2 + 1 + 2 + 1 + 2));
+
+        let mut xdr_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data))?;
+        let mut ints = [0; 3];
+        xdr_read.read_int(&mut ints)?;
+        assert_eq!(ints, [-1, 2, 3]);
+        let mut uint = [0];
+        xdr_read.read_uint(&mut uint)?;
+        assert_eq!(uint, [u32::MAX]);
+        let (mut short, mut ushort, mut char, mut uchar) = ([0], [0], [0 as c_char], [0]);
+        xdr_read.read_short(&mut short)?;
+        xdr_read.read_ushort(&mut ushort)?;
+        xdr_read.read_char(&mut char)?;
+        xdr_read.read_uchar(&mut uchar)?;
+        assert_eq!((short, ushort, char, uchar), ([-7], [65535], [b'x' as c_char], [200]));
+        let mut floats = [0.; 2];
+        xdr_read.read_float(&mut floats)?;
+        assert_eq!(floats, [1.5, -2.25]);
+        let mut double = [0.];
+        xdr_read.read_double(&mut double)?;
+        assert_eq!(double, [std::f64::consts::PI]);
+        assert_eq!(xdr_read.read_string(16)?, "sidecar");
+        let mut opaque = [0; 5];
+        xdr_read.read_opaque(&mut opaque)?;
+        assert_eq!(opaque, [1, 2, 3, 4, 5]);
+
+        // Strings which are too long or aren't UTF-8 are rejected
+        assert_eq!(xdr_read.read_string(4).unwrap_err(), XDRStatus::exdrSTRING);
+        let mut bytes = [0; 8];
+        xdr_read.read_opaque(&mut bytes)?;
+        assert_eq!(&bytes, b"too long");
+        assert_eq!(xdr_read.read_string(16).unwrap_err(), XDRStatus::exdrSTRING);
+        assert_eq!(xdr_read.read_int(&mut ints).unwrap_err(), XDRStatus::exdrINT);
+
+        // The length of a string isn't allocated before the data is found to be shorter
+        let mut data = u32::MAX.to_be_bytes().to_vec();
+        data.extend(b"abcd");
+        let mut xdr_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data))?;
+        assert_eq!(xdr_read.read_string(usize::MAX).unwrap_err(), XDRStatus::exdrSTRING);
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    /// Test serializing and deserializing values with serde, and rejecting invalid data
+    fn test_serde() -> Result<(), Error> {
+        use std::{collections::BTreeMap, io::Cursor};
+        use ::serde::{Deserialize, Serialize};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        enum Thermostat {
+            None,
+            VRescale { tau_t: f32 },
+            NoseHoover(f32, u8),
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct RunInfo {
+            name: String,
+            nsteps: i64,
+            dt: f64,
+            restarted: bool,
+            seed: Option<u32>,
+            groups: Vec<(char, u16)>,
+            thermostats: BTreeMap<String, Thermostat>,
+            checksum: [u8; 3],
+        }
+
+        let info = RunInfo {
+            name: "npt".to_string(),
+            nsteps: -1 << 40,
+            dt: 0.002,
+            restarted: true,
+            seed: None,
+            groups: vec![('P', 12), ('S', 9000)],
+            thermostats: BTreeMap::from([
+                ("none".to_string(), Thermostat::None),
+                ("protein".to_string(), Thermostat::VRescale { tau_t: 0.1 }),
+                ("sol".to_string(), Thermostat::NoseHoover(0.5, 10)),
+            ]),
+            checksum: [1, 2, 3],
+        };
+
+        let mut xdr_write = XDRFile::<access_mode::Write>::from_writer(Cursor::new(Vec::new()))?;
+        xdr_write.serialize(&info)?;
+        xdr_write.serialize("trailer")?;
+        let data = xdr_write.into_inner::<Cursor<Vec<u8>>>()?.into_inner();
+        assert_eq!(data.len() % 4, 0);
+        // Length-prefixed, padded string followed by a big-endian hyper
+        assert_eq!(&data[..16], &[0, 0, 0, 3, b'n', b'p', b't', 0, 0xff, 0xff, 0xff, 0, 0, 0, 0, 0]);
+
+        let mut xdr_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data.clone()))?;
+        assert_eq!(xdr_read.deserialize::<RunInfo>()?, info);
+        assert_eq!(xdr_read.deserialize::<String>()?, "trailer");
+        assert!(xdr_read.deserialize::<u32>().unwrap_err().status().is_some());
+
+        // Invalid values are rejected
+        let mut xdr_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(vec![0, 0, 1, 0]))?;
+        assert!(matches!(xdr_read.deserialize::<u8>().unwrap_err().kind(), ErrorKind::Overflow));
+        let mut xdr_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(vec![0, 0, 0, 2]))?;
+        assert!(matches!(xdr_read.deserialize::<bool>().unwrap_err().kind(), ErrorKind::Custom(_)));
+
+        // Lengths longer than the data are rejected without being allocated
+        let data = [0xff, 0xff, 0xff, 0xff, b'a', b'b', b'c', b'd'];
+        let mut xdr_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data.to_vec()))?;
+        assert_eq!(xdr_read.deserialize::<String>().unwrap_err(), XDRStatus::exdrSTRING);
+        let mut xdr_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data.to_vec()))?;
+        let mut deserializer = crate::serde::Deserializer::new(&mut xdr_read);
+        let err = ::serde::Deserializer::deserialize_byte_buf(&mut deserializer, ::serde::de::IgnoredAny).unwrap_err();
+        assert_eq!(err, XDRStatus::exdrSTRING);
+        Ok(())
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    /// Test derived encoding and decoding, including field attributes and enum discriminants
+    fn test_derive() -> Result<(), Error> {
+        use std::io::Cursor;
+        use crate::codec::{XdrDecode, XdrEncode};
+
+        #[derive(Debug, PartialEq, XdrEncode, XdrDecode)]
+        struct TrrHeader {
+            magic: i32,
+            version_len: i32,
+            version: String,
+            #[xdr(fixed = 7)]
+            ir_to_sym_sizes: Vec<i32>,
+            x_size: i32,
+            v_size: i32,
+            f_size: i32,
+            natoms: i32,
+            step: i32,
+            nre: i32,
+            t: f32,
+            lambda: f32,
+        }
+
+        // Read a header written by the trr writer
+        let mut xdr_write = XDRFile::<access_mode::Write>::from_writer(Cursor::new(Vec::new()))?;
+        let x = vec![rvec([1., 2., 3.]); 5];
+        xdr_write.write_trr(25, 0.5, 0.25, matrix::new(), Some(&x), None, None)?;
+        let data = xdr_write.into_inner::<Cursor<Vec<u8>>>()?.into_inner();
+        let mut xdr_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data.clone()))?;
+        let header: TrrHeader = xdr_read.decode()?;
+        assert_eq!(header.magic, crate::trr::MAGIC);
+        assert_eq!(header.version, "GMX_trn_file");
+        assert_eq!(header.ir_to_sym_sizes[2], 36);
+        assert_eq!((header.x_size, header.v_size, header.natoms), (60, 0, 5));
+        assert_eq!((header.step, header.t, header.lambda), (25, 0.5, 0.25));
+
+        // And write it back out identically
+        let mut xdr_write = XDRFile::<access_mode::Write>::from_writer(Cursor::new(Vec::new()))?;
+        xdr_write.encode(&header)?;
+        let header_data = xdr_write.into_inner::<Cursor<Vec<u8>>>()?.into_inner();
+        assert_eq!(header_data, data[..header_data.len()]);
+
+        #[derive(Debug, PartialEq, XdrEncode, XdrDecode)]
+        #[repr(i32)]
+        enum Block {
+            Empty,
+            Energies(Vec<f64>, u8),
+            Named { name: String, #[xdr(opaque)] data: Vec<u8> } = 10,
+            Tagged([u8; 2]),
+        }
+
+        #[derive(Debug, PartialEq, XdrEncode, XdrDecode)]
+        struct Record<T> {
+            #[xdr(opaque)]
+            magic: [u8; 3],
+            #[xdr(pad = 8)]
+            version: u16,
+            #[xdr(opaque, fixed = 2)]
+            flags: Vec<u8>,
+            #[xdr(skip)]
+            cached: usize,
+            id: Option<i64>,
+            value: T,
+            blocks: Vec<Block>,
+        }
+
+        let record = Record {
+            magic: *b"edr",
+            version: 5,
+            flags: vec![1, 2],
+            cached: 0,
+            id: Some(-2),
+            value: true,
+            blocks: vec![
+                Block::Empty,
+                Block::Energies(vec![1.5, -2.5], 9),
+                Block::Named { name: "box".to_string(), data: vec![7; 5] },
+                Block::Tagged([3, 4]),
+            ],
+        };
+        let mut xdr_write = XDRFile::<access_mode::Write>::from_writer(Cursor::new(Vec::new()))?;
+        record.encode(&mut xdr_write)?;
+        let invalid = Record { flags: vec![1, 2, 3], ..record };
+        assert!(matches!(xdr_write.encode(&invalid).unwrap_err().kind(), ErrorKind::Custom(_)));
+        let data = xdr_write.into_inner::<Cursor<Vec<u8>>>()?.into_inner();
+        let record = Record { flags: vec![1, 2], ..invalid };
+        assert_eq!(&data[..20], b"edr\0\0\0\0\x05\0\0\0\0\0\0\0\0\x01\x02\0\0");
+        // Discriminants follow explicit values
+        assert_eq!(&data[72..76], &[0, 0, 0, 10]);
+        assert_eq!(&data[96..100], &[0, 0, 0, 11]);
+
+        let mut xdr_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data))?;
+        assert_eq!(Record::<bool>::decode(&mut xdr_read)?, record);
+        let mut xdr_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(vec![0, 0, 0, 3]))?;
+        assert!(matches!(xdr_read.decode::<Block>().unwrap_err().kind(), ErrorKind::Custom(_)));
+        // Opaque data longer than the file is rejected without being allocated
+        let data = vec![0, 0, 0, 10, 0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 1, 2, 3, 4];
+        let mut xdr_read = XDRFile::<access_mode::Read>::from_reader(Cursor::new(data))?;
+        assert_eq!(xdr_read.decode::<Block>().unwrap_err(), XDRStatus::exdrSTRING);
+        Ok(())
+    }
+
+    #[test]
+    /// Test reading energy files in single and double precision, and extracting time series
+    fn test_edr() -> Result<(), Error> {
+        use std::io::Cursor;
+
+        // Write an energy file in the layout used by GROMACS, with reals in the given precision
+        fn write_edr(double: bool) -> Result<Vec<u8>, Error> {
+            let mut xdr = XDRFile::<access_mode::Write>::from_writer(Cursor::new(Vec::new()))?;
+            xdr.write_int(&[-55555, 5, 3])?;
+            for (name, unit) in [("Potential", "kJ/mol"), ("Pressure", "bar"), ("Volume", "nm^3")] {
+                xdr.write_string(name, 64)?;
+                xdr.write_string(unit, 64)?;
+            }
+            let real = |xdr: &mut XDRFile<access_mode::Write>, x: f64| {
+                if double { xdr.write_double(&[x]) } else { xdr.write_float(&[x as f32]) }
+            };
+            for (i, (nsum, nre, nblock)) in [(0, 3, 0), (2, 3, 0), (0, 0, 1)].into_iter().enumerate() {
+                real(&mut xdr, -2e10)?;
+                xdr.write_int(&[-7777777, 5])?;
+                xdr.write_double(&[i as f64 * 0.5])?;
+                xdr.encode(&(i as i64 * 250))?;
+                xdr.write_int(&[nsum])?;
+                xdr.encode(&250i64)?;
+                xdr.write_double(&[0.002])?;
+                xdr.write_int(&[nre, 0, nblock])?;
+                if nblock > 0 {
+                    // Block with double, int64, string and char sub-blocks
+                    xdr.write_int(&[6, 4, 2, 2, 3, 1, 5, 1, 4, 2])?;
+                }
+                xdr.write_int(&[0, 0, 0])?;
+                for term in 0..nre {
+                    let e = -100. * (i + 1) as f64 + term as f64;
+                    real(&mut xdr, e)?;
+                    if nsum > 0 {
+                        real(&mut xdr, e + 0.5)?;
+                        real(&mut xdr, 2. * e + 1.)?;
+                    }
+                }
+                if nblock > 0 {
+                    xdr.write_double(&[0.25, 0.75])?;
+                    xdr.encode(&-3i64)?;
+                    xdr.write_int(&[5])?;
+                    xdr.write_string("dhdl", 64)?;
+                    xdr.write_uchar(&[1, 2])?;
+                }
+            }
+            Ok(xdr.into_inner::<Cursor<Vec<u8>>>()?.into_inner())
+        }
+
+        for double in [false, true] {
+            let data = write_edr(double)?;
+            let mut edr = EdrReader::new(XDRFile::from_reader(Cursor::new(data.clone()))?)?;
+            assert_eq!(edr.terms().len(), 3);
+            assert_eq!(edr.terms()[1], EnergyTerm { name: "Pressure".to_string(), unit: "bar".to_string() });
+            assert_eq!(edr.term_index("Volume"), Some(2));
+            assert_eq!(edr.is_double(), None);
+
+            let frames = edr.by_ref().collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(edr.is_double(), Some(double));
+            assert_eq!(frames.len(), 3);
+            assert_eq!((frames[1].time, frames[1].step, frames[1].nsteps, frames[1].dt), (0.5, 250, 250, 0.002));
+            assert_eq!(frames[0].energies[2], Energy { e: -98., eav: 0., esum: 0. });
+            assert_eq!(frames[1].energies[0], Energy { e: -200., eav: -199.5, esum: -399. });
+            assert!(frames[2].energies.is_empty());
+            assert_eq!(frames[2].blocks, vec![EnergyBlock {
+                id: 6,
+                sub: vec![
+                    EnergySubBlock::Double(vec![0.25, 0.75]),
+                    EnergySubBlock::Int64(vec![-3]),
+                    EnergySubBlock::String(vec!["dhdl".to_string()]),
+                    EnergySubBlock::Char(vec![1, 2]),
+                ],
+            }]);
+
+            // Time series skip frames without energies
+            let mut edr = EdrReader::new(XDRFile::from_reader(Cursor::new(data.clone()))?)?;
+            assert!(edr.series(&["Potential", "Density"]).is_err());
+            let series = edr.series(&["Volume", "Potential"])?;
+            assert_eq!(series.time, [0., 0.5]);
+            assert_eq!(series.step, [0, 250]);
+            assert_eq!(series.get("Potential"), Some(&[-100., -200.][..]));
+            assert_eq!(series.values[0], [-98., -198.]);
+            let mut xvg = Vec::new();
+            series.to_writer(&mut xvg)?;
+            let xvg = String::from_utf8(xvg).unwrap();
+            assert!(xvg.contains("@    yaxis  label \"(nm^3), (kJ/mol)\"\n@TYPE xy\n@ s0 legend \"Volume\"\n"));
+            assert!(xvg.ends_with("    0.500000   -198.000000   -200.000000\n"));
+
+            // A truncated frame is an error, with the frame and where it starts
+            let mut edr = EdrReader::new(XDRFile::from_reader(Cursor::new(data[..data.len() - 8].to_vec()))?)?;
+            let frame_2 = {
+                edr.read_frame()?;
+                edr.read_frame()?;
+                edr.into_inner().offset()
+            };
+            let mut edr = EdrReader::new(XDRFile::from_reader(Cursor::new(data[..data.len() - 8].to_vec()))?)?;
+            let e = edr.nth(2).unwrap().unwrap_err();
+            assert_eq!((e.frame(), e.offset()), (Some(2), frame_2));
+            assert!(edr.next().is_none());
+        }
+
+        let mut bad = write_edr(false)?;
+        bad[0] = 0;
+        assert_eq!(EdrReader::new(XDRFile::from_reader(Cursor::new(bad))?).err().unwrap(), XDRStatus::exdrMAGIC);
+        Ok(())
     }
 }
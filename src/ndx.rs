@@ -0,0 +1,134 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{Error, ErrorKind, selection::Selection};
+
+/// Named group of atoms from a GROMACS index (`.ndx`) file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexGroup {
+    pub name: String,
+    /// Atom indices, counting from 0. These are stored counting from 1 in the file.
+    pub atoms: Vec<usize>,
+}
+
+impl IndexGroup {
+    pub fn new(name: impl Into<String>, atoms: Vec<usize>) -> Self {
+        Self { name: name.into(), atoms }
+    }
+
+    /// Selection of the atoms in this group, for reading or writing a subset of each frame
+    pub fn selection(&self) -> Selection {
+        Selection::from(&self.atoms[..])
+    }
+}
+
+impl From<&IndexGroup> for Selection {
+    fn from(group: &IndexGroup) -> Self {
+        group.selection()
+    }
+}
+
+/// Contents of a GROMACS index (`.ndx`) file, which is a list of named groups of atoms.
+/// Group names don't have to be unique.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexFile {
+    pub groups: Vec<IndexGroup>,
+}
+
+impl IndexFile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read an index file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_reader(File::open(path)?)
+    }
+
+    /// Parse an index file from a stream
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        let mut groups: Vec<IndexGroup> = Vec::new();
+        for (i, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let parse_error = |message: String| Error::from(ErrorKind::Parse { line: i + 1, message });
+            let line = line.trim();
+            if let Some(name) = line.strip_prefix('[') {
+                let Some(name) = name.strip_suffix(']') else {
+                    return Err(parse_error("missing ']' after group name".into()))
+                };
+                groups.push(IndexGroup::new(name.trim(), Vec::new()));
+                continue
+            }
+            if line.is_empty() {
+                continue
+            }
+            let Some(group) = groups.last_mut() else {
+                return Err(parse_error("atoms found before the first group name".into()))
+            };
+            for atom in line.split_whitespace() {
+                match atom.parse::<usize>() {
+                    Ok(n) if n > 0 => group.atoms.push(n - 1),
+                    _ => return Err(parse_error(format!("invalid atom number '{}'", atom))),
+                }
+            }
+        }
+        Ok(Self { groups })
+    }
+
+    /// Write the index file to `path`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.to_writer(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the index file to a stream, in the same layout as GROMACS
+    pub fn to_writer<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        for group in &self.groups {
+            write!(writer, "[ {} ]", group.name)?;
+            for (k, atom) in group.atoms.iter().enumerate() {
+                let sep = if k % 15 == 0 { '\n' } else { ' ' };
+                write!(writer, "{}{:4}", sep, atom + 1)?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Get the first group called `name`
+    pub fn get(&self, name: &str) -> Option<&IndexGroup> {
+        self.groups.iter().find(|group| group.name == name)
+    }
+
+    /// Add a group to the end of the file
+    pub fn push(&mut self, group: IndexGroup) {
+        self.groups.push(group);
+    }
+
+    /// Number of groups
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Whether there are no groups
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, IndexGroup> {
+        self.groups.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a IndexFile {
+    type Item = &'a IndexGroup;
+    type IntoIter = std::slice::Iter<'a, IndexGroup>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.groups.iter()
+    }
+}
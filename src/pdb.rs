@@ -0,0 +1,343 @@
+use std::{
+    ffi::c_float,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Lines, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    Error, ErrorKind, XTCFrame,
+    gro::title_step_time,
+    xdr::*,
+};
+
+/// Number of Angstrom per nm. PDB files use Angstrom, while the rest of this crate uses nm.
+const ANGSTROM_PER_NM: c_float = 10.;
+
+/// An `ATOM` or `HETATM` record, without its coordinates
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdbAtom {
+    /// Whether this is a `HETATM` record rather than `ATOM`
+    pub hetatm: bool,
+    pub serial: i32,
+    pub name: String,
+    pub alt_loc: char,
+    pub res_name: String,
+    pub chain_id: char,
+    pub res_seq: i32,
+    pub i_code: char,
+    pub occupancy: c_float,
+    pub temp_factor: c_float,
+    pub element: String,
+    pub charge: String,
+}
+
+/// One model of a PDB file
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdbModel {
+    /// Model number from the `MODEL` record, or 1 if there isn't one
+    pub number: i32,
+    /// Text of the `TITLE` records for this model
+    pub title: String,
+    /// Box from the most recent `CRYST1` record, in nm
+    pub sim_box: Option<matrix>,
+    pub atoms: Vec<PdbAtom>,
+    /// Atom positions in nm
+    pub x: Vec<rvec>,
+}
+
+impl PdbModel {
+    /// Convert to an xtc frame. The step and time are taken from the title if it contains
+    /// `step=` and `t=`, as written by GROMACS. Otherwise the step is `index` and the time is 0.
+    pub fn to_xtc_frame(&self, index: usize) -> XTCFrame {
+        let (step, time) = title_step_time(&self.title);
+        XTCFrame {
+            step: step.unwrap_or(index as _),
+            time: time.unwrap_or(0.),
+            sim_box: self.sim_box.unwrap_or_default(),
+            x: self.x.clone(),
+            ..XTCFrame::empty()
+        }
+    }
+}
+
+/// Contents of a PDB file, which may contain several models
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PdbFile {
+    pub models: Vec<PdbModel>,
+}
+
+impl PdbFile {
+    /// Read every model of a PDB file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_reader(File::open(path)?)
+    }
+
+    /// Read every model of a PDB file from a stream
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        Ok(Self { models: PdbReader::new(reader).collect::<Result<_, _>>()? })
+    }
+
+    /// Write the PDB file to `path`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.to_writer(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the PDB file to a stream. `MODEL` records are only written if there is more than
+    /// one model.
+    pub fn to_writer<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        for model in &self.models {
+            if model.atoms.len() != model.x.len() {
+                return Err(ErrorKind::NatomsMismatch { expected: model.atoms.len(), found: model.x.len() }.into())
+            }
+            if !model.title.is_empty() {
+                writeln!(writer, "TITLE     {}", model.title)?;
+            }
+            if let Some(sim_box) = &model.sim_box {
+                let [a, b, c, alpha, beta, gamma] = box_to_cryst1(sim_box);
+                writeln!(
+                    writer,
+                    "CRYST1{:9.3}{:9.3}{:9.3}{:7.2}{:7.2}{:7.2} P 1           1",
+                    a * ANGSTROM_PER_NM, b * ANGSTROM_PER_NM, c * ANGSTROM_PER_NM, alpha, beta, gamma
+                )?;
+            }
+            if self.models.len() > 1 {
+                writeln!(writer, "MODEL     {:4}", model.number)?;
+            }
+            for (atom, x) in model.atoms.iter().zip(&model.x) {
+                // Names shorter than 4 characters start in the second column of the field
+                let name = if atom.name.len() < 4 { format!(" {}", atom.name) } else { atom.name.clone() };
+                writeln!(
+                    writer,
+                    "{:<6}{:>5} {:<4.4}{}{:>3.3} {}{:>4}{}   {:8.3}{:8.3}{:8.3}{:6.2}{:6.2}          {:>2.2}{:<2.2}",
+                    if atom.hetatm { "HETATM" } else { "ATOM" },
+                    atom.serial.rem_euclid(100000),
+                    name,
+                    atom.alt_loc,
+                    atom.res_name,
+                    atom.chain_id,
+                    atom.res_seq.rem_euclid(10000),
+                    atom.i_code,
+                    x.0[0] * ANGSTROM_PER_NM,
+                    x.0[1] * ANGSTROM_PER_NM,
+                    x.0[2] * ANGSTROM_PER_NM,
+                    atom.occupancy,
+                    atom.temp_factor,
+                    atom.element,
+                    atom.charge,
+                )?;
+            }
+            writeln!(writer, "TER")?;
+            if self.models.len() > 1 {
+                writeln!(writer, "ENDMDL")?;
+            }
+        }
+        writeln!(writer, "END")?;
+        Ok(())
+    }
+}
+
+/// Reader which parses one model of a PDB file at a time, so multi-model files can be used as
+/// a trajectory. Records other than `TITLE`, `CRYST1`, `MODEL`, `ATOM`, `HETATM` and `ENDMDL`
+/// are ignored. As with `CRYST1`, a model without a `TITLE` has the title of the model before.
+pub struct PdbReader<R: Read> {
+    lines: Lines<BufReader<R>>,
+    line_number: usize,
+    /// Box from the most recent `CRYST1` record
+    sim_box: Option<matrix>,
+    /// Title from the most recent `TITLE` records, which applies to later models without one
+    title: String,
+    done: bool,
+}
+
+impl<R: Read> PdbReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: BufReader::new(reader).lines(),
+            line_number: 0,
+            sim_box: None,
+            title: String::new(),
+            done: false,
+        }
+    }
+
+    /// Iterate over the remaining models as xtc frames. See [`PdbModel::to_xtc_frame`].
+    pub fn xtc_frames(self) -> impl Iterator<Item = Result<XTCFrame, Error>> {
+        self.enumerate().map(|(i, model)| model.map(|model| model.to_xtc_frame(i)))
+    }
+
+    /// Read the next model, or `None` at the end of the file
+    fn read_model(&mut self) -> Result<Option<PdbModel>, Error> {
+        let mut model: Option<PdbModel> = None;
+        let mut title = String::new();
+        for line in self.lines.by_ref() {
+            let line = line?;
+            self.line_number += 1;
+            let n = self.line_number;
+            let record = line.get(..6).unwrap_or(&line).trim_end();
+            match record {
+                "TITLE" => {
+                    let text = line.get(10..).unwrap_or("").trim();
+                    if !title.is_empty() {
+                        title.push(' ');
+                    }
+                    title.push_str(text);
+                }
+                "CRYST1" => {
+                    let mut cryst1 = [0.; 6];
+                    let widths = [(6, 15), (15, 24), (24, 33), (33, 40), (40, 47), (47, 54)];
+                    for (v, (start, end)) in cryst1.iter_mut().zip(widths) {
+                        *v = parse_field(&line, start, end, n, "CRYST1 value")?;
+                    }
+                    for v in &mut cryst1[..3] {
+                        *v /= ANGSTROM_PER_NM;
+                    }
+                    let [a, b, c, alpha, beta, gamma] = cryst1;
+                    self.sim_box = Some(box_from_cryst1(a, b, c, alpha, beta, gamma));
+                }
+                "MODEL" => {
+                    if model.as_ref().is_some_and(|m| !m.atoms.is_empty()) {
+                        return Err(parse_error(n, "MODEL inside another model".into()))
+                    }
+                    let number = line.get(6..).unwrap_or("").trim().parse()
+                        .map_err(|_| parse_error(n, "invalid model number".into()))?;
+                    model = Some(PdbModel {
+                        number,
+                        title: String::new(),
+                        sim_box: None,
+                        atoms: Vec::new(),
+                        x: Vec::new(),
+                    });
+                }
+                "ATOM" | "HETATM" => {
+                    let (atom, x) = parse_atom(&line, n)?;
+                    let model = model.get_or_insert_with(|| PdbModel {
+                        number: 1,
+                        title: String::new(),
+                        sim_box: None,
+                        atoms: Vec::new(),
+                        x: Vec::new(),
+                    });
+                    model.atoms.push(atom);
+                    model.x.push(x);
+                }
+                "ENDMDL" if model.is_some() => break,
+                _ => (),
+            }
+        }
+        if !title.is_empty() {
+            self.title = title;
+        }
+        Ok(model.map(|mut model| {
+            model.title = self.title.clone();
+            model.sim_box = self.sim_box;
+            model
+        }))
+    }
+}
+
+impl<R: Read> Iterator for PdbReader<R> {
+    type Item = Result<PdbModel, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None
+        }
+        let result = self.read_model().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+impl<R: Read> std::iter::FusedIterator for PdbReader<R> {}
+
+fn parse_error(line: usize, message: String) -> Error {
+    ErrorKind::Parse { line, message }.into()
+}
+
+/// Parse the fixed width field between columns `start` and `end` (counting from 0)
+fn parse_field<T: std::str::FromStr>(line: &str, start: usize, end: usize, n: usize, what: &str) -> Result<T, Error> {
+    let s = line.get(start..end.min(line.len())).unwrap_or("").trim();
+    s.parse().map_err(|_| parse_error(n, format!("invalid {} '{}'", what, s)))
+}
+
+/// Parse an `ATOM` or `HETATM` record, returning the atom and its position in nm
+fn parse_atom(line: &str, n: usize) -> Result<(PdbAtom, rvec), Error> {
+    let text = |start: usize, end: usize| line.get(start..end.min(line.len())).unwrap_or("").trim().to_string();
+    let char_at = |i: usize| line.as_bytes().get(i).map_or(' ', |&c| c as char);
+    let mut x = rvec::new();
+    for (d, c) in x.0.iter_mut().enumerate() {
+        *c = parse_field::<c_float>(line, 30 + 8 * d, 38 + 8 * d, n, "coordinate")? / ANGSTROM_PER_NM;
+    }
+    // Occupancy and temperature factor are often missing
+    let optional = |start: usize, end: usize, default: c_float| {
+        let s = text(start, end);
+        if s.is_empty() { Ok(default) } else { parse_field(line, start, end, n, "number") }
+    };
+    let atom = PdbAtom {
+        hetatm: line.starts_with("HETATM"),
+        serial: parse_field(line, 6, 11, n, "atom serial number")?,
+        name: text(12, 16),
+        alt_loc: char_at(16),
+        res_name: text(17, 20),
+        chain_id: char_at(21),
+        res_seq: parse_field(line, 22, 26, n, "residue number")?,
+        i_code: char_at(26),
+        occupancy: optional(54, 60, 1.)?,
+        temp_factor: optional(60, 66, 0.)?,
+        element: text(76, 78),
+        charge: text(78, 80),
+    };
+    Ok((atom, x))
+}
+
+/// Convert box lengths and angles (in degrees), as in a `CRYST1` record, to a box matrix.
+/// The first box vector lies along x and the second in the xy plane, as in GROMACS.
+/// The box has the same length units as `a`, `b` and `c`.
+pub fn box_from_cryst1(a: c_float, b: c_float, c: c_float, alpha: c_float, beta: c_float, gamma: c_float) -> matrix {
+    let mut m = matrix::new();
+    m.0[0][0] = a;
+    if alpha == 90. && beta == 90. && gamma == 90. {
+        m.0[1][1] = b;
+        m.0[2][2] = c;
+        return m
+    }
+    let (alpha, beta, gamma) = ((alpha as f64).to_radians(), (beta as f64).to_radians(), (gamma as f64).to_radians());
+    let (b, c) = (b as f64, c as f64);
+    m.0[1][0] = (b * gamma.cos()) as c_float;
+    m.0[1][1] = (b * gamma.sin()) as c_float;
+    let zx = c * beta.cos();
+    let zy = c * (alpha.cos() - beta.cos() * gamma.cos()) / gamma.sin();
+    m.0[2][0] = zx as c_float;
+    m.0[2][1] = zy as c_float;
+    m.0[2][2] = (c * c - zx * zx - zy * zy).max(0.).sqrt() as c_float;
+    m
+}
+
+/// Convert a box matrix to box lengths and angles (in degrees), as in a `CRYST1` record.
+/// Returns `[a, b, c, alpha, beta, gamma]`.
+pub fn box_to_cryst1(m: &matrix) -> [c_float; 6] {
+    let v = m.0.map(|r| r.map(|x| x as f64));
+    let norm = |r: &[f64; DIM]| r.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let angle = |r1: &[f64; DIM], r2: &[f64; DIM]| {
+        let (n1, n2) = (norm(r1), norm(r2));
+        if n1 == 0. || n2 == 0. {
+            return 90.
+        }
+        let cos = r1.iter().zip(r2).map(|(x, y)| x * y).sum::<f64>() / (n1 * n2);
+        cos.clamp(-1., 1.).acos().to_degrees()
+    };
+    [
+        norm(&v[0]) as c_float,
+        norm(&v[1]) as c_float,
+        norm(&v[2]) as c_float,
+        angle(&v[1], &v[2]) as c_float,
+        angle(&v[0], &v[2]) as c_float,
+        angle(&v[0], &v[1]) as c_float,
+    ]
+}
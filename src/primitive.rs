@@ -0,0 +1,82 @@
+use std::ffi::{c_char, c_uint, c_uchar, c_short, c_ushort, c_int, c_float, c_double};
+
+use crate::{
+    Error, XDRAccessMode, XDRFile, access_mode,
+    xdr::*,
+};
+
+/// Generate safe methods for reading/writing slices of an XDR primitive type, which fail unless
+/// every value is transferred
+macro_rules! xdr_safe_rw {
+    ($read:ident, $write:ident, $t:ty, $status:ident, $name:literal) => {
+        impl XDRFile<access_mode::Read> {
+            #[doc = concat!("Read `values.len()` ", $name, ", failing if they can't all be read")]
+            pub fn $read(&mut self, values: &mut [$t]) -> Result<(), Error> {
+                let offset = self.offset();
+                if self.handle.$read(values) != values.len() {
+                    return Err(Error::from(XDRStatus::$status).at(None, offset))
+                }
+                Ok(())
+            }
+        }
+
+        impl<MODE: XDRAccessMode + access_mode::Writable> XDRFile<MODE> {
+            #[doc = concat!("Write all of `values` as ", $name, ", failing if they can't all be written")]
+            pub fn $write(&mut self, values: &[$t]) -> Result<(), Error> {
+                let offset = self.offset();
+                if self.handle.$write(values) != values.len() {
+                    return Err(Error::from(XDRStatus::$status).at(None, offset))
+                }
+                Ok(())
+            }
+        }
+    };
+}
+
+xdr_safe_rw!(read_char, write_char, c_char, exdrINT, "chars");
+xdr_safe_rw!(read_uchar, write_uchar, c_uchar, exdrUINT, "unsigned chars");
+xdr_safe_rw!(read_short, write_short, c_short, exdrINT, "shorts");
+xdr_safe_rw!(read_ushort, write_ushort, c_ushort, exdrUINT, "unsigned shorts");
+xdr_safe_rw!(read_int, write_int, c_int, exdrINT, "ints");
+xdr_safe_rw!(read_uint, write_uint, c_uint, exdrUINT, "unsigned ints");
+xdr_safe_rw!(read_float, write_float, c_float, exdrFLOAT, "floats");
+xdr_safe_rw!(read_double, write_double, c_double, exdrDOUBLE, "doubles");
+xdr_safe_rw!(read_opaque, write_opaque, u8, exdrSTRING, "bytes of opaque data, padded to a multiple of 4 bytes");
+
+impl XDRFile<access_mode::Read> {
+    /// Read a string of at most `max_len` bytes.
+    /// Fails without reading the string if it is longer than `max_len`, leaving the position in
+    /// the file after the length of the string. Fails after reading the string if it isn't valid
+    /// UTF-8, leaving the position after the string.
+    pub fn read_string(&mut self, max_len: usize) -> Result<String, Error> {
+        let offset = self.offset();
+        let error = || Error::from(XDRStatus::exdrSTRING).at(None, offset);
+        let mut len = [0];
+        self.read_uint(&mut len)?;
+        let len = len[0] as usize;
+        if len > max_len {
+            return Err(error())
+        }
+        let mut bytes = Vec::new();
+        if !self.handle.read_opaque_vec(&mut bytes, len) {
+            return Err(error())
+        }
+        String::from_utf8(bytes).map_err(|_| error())
+    }
+}
+
+impl<MODE: XDRAccessMode + access_mode::Writable> XDRFile<MODE> {
+    /// Write a string, which is stored as its length followed by the bytes of the string, padded
+    /// to a multiple of 4 bytes.
+    /// Fails without writing anything if the string is longer than `max_len` bytes.
+    pub fn write_string(&mut self, s: &str, max_len: usize) -> Result<(), Error> {
+        let offset = self.offset();
+        let error = || Error::from(XDRStatus::exdrSTRING).at(None, offset);
+        let Ok(len) = c_uint::try_from(s.len()) else { return Err(error()) };
+        if s.len() > max_len {
+            return Err(error())
+        }
+        self.write_uint(&[len])?;
+        self.write_opaque(s.as_bytes())
+    }
+}
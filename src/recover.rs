@@ -0,0 +1,159 @@
+use std::ops::Range;
+
+use crate::{
+    Error, ErrorKind, XDRFile, XTCFrame, access_mode,
+    check::{TrajectoryFormat, resync, seek_to},
+    frames::read_header,
+};
+
+/// Part of an xtc file which couldn't be read as frames, so was skipped
+#[derive(Debug)]
+pub struct SkippedBytes {
+    /// Byte offsets of the skipped data. This ends at the start of the next frame which could be
+    /// read, or at the end of the file.
+    pub range: Range<u64>,
+    /// Error from reading the frame at the start of the skipped data
+    pub error: Error,
+}
+
+/// Item of the iterator returned by [`XDRFile::recover_frames`]
+#[derive(Debug)]
+pub enum Recovered {
+    Frame(XTCFrame),
+    Skipped(SkippedBytes),
+}
+
+impl XDRFile<access_mode::Read> {
+    /// Iterate over the remaining frames of an xtc file, skipping over damaged data rather than
+    /// stopping at the first frame which can't be read.
+    ///
+    /// Every frame must contain `natoms` atoms, or the same number of atoms as the first frame
+    /// read if `natoms` is `None`. When a frame can't be read, the file is searched byte by byte
+    /// for the next frame header with the magic number and the right number of atoms, which must
+    /// be followed by another magic number or the end of the file, and whose coordinates must
+    /// decompress. The range of bytes skipped is returned, then reading carries on from that
+    /// frame. Frame numbers in errors are unknown once data has been skipped.
+    ///
+    /// Searching needs a seekable file, so for other streams the first damaged frame is returned
+    /// as an error, after which the iterator is exhausted, as are errors from seeking.
+    ///
+    /// ```no_run
+    /// # use xdrfile::prelude::*;
+    /// # fn main() -> Result<(), Error> {
+    /// let mut xtc = XDRFile::<access_mode::Read>::open(c"traj.xtc")?;
+    /// for item in xtc.recover_frames(None) {
+    ///     match item? {
+    ///         Recovered::Frame(frame) => println!("{} {}", frame.step, frame.x.len()),
+    ///         Recovered::Skipped(skipped) => eprintln!("skipped {:?}: {}", skipped.range, skipped.error),
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn recover_frames(&mut self, natoms: Option<usize>) -> RecoverFrames<'_> {
+        RecoverFrames {
+            file: self,
+            natoms,
+            pending: None,
+            done: false,
+        }
+    }
+
+    /// Read the frame starting at `offset`, checking that it contains `natoms` atoms before
+    /// allocating space for them.
+    fn read_xtc_at(&mut self, offset: u64, natoms: Option<usize>) -> Result<XTCFrame, Error> {
+        let file_size = self.handle.stream_len()?;
+        seek_to(&mut self.handle, offset).map_err(|e| self.error_at(e, Some(offset)))?;
+        let header = read_header(&mut self.handle, Some(file_size)).map_err(|e| self.error_at(e, Some(offset)))?;
+        if let Some(expected) = natoms.filter(|&n| n != header.natoms) {
+            let kind = ErrorKind::NatomsMismatch { expected, found: header.natoms };
+            return Err(self.error_at(kind, Some(offset)))
+        }
+        seek_to(&mut self.handle, offset).map_err(|e| self.error_at(e, Some(offset)))?;
+        let mut frame = XTCFrame::empty();
+        self.read_xtc_next(&mut frame)?;
+        Ok(frame)
+    }
+}
+
+/// Iterator over the frames of an xtc file, which skips damaged data.
+/// See [`XDRFile::recover_frames`].
+pub struct RecoverFrames<'a> {
+    file: &'a mut XDRFile<access_mode::Read>,
+    natoms: Option<usize>,
+    /// Frame found after skipping data, which is returned after the skipped range
+    pending: Option<XTCFrame>,
+    done: bool,
+}
+
+impl Iterator for RecoverFrames<'_> {
+    type Item = Result<Recovered, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(frame) = self.pending.take() {
+            return Some(Ok(Recovered::Frame(frame)))
+        }
+        if self.done {
+            return None
+        }
+        let start = self.file.handle.position();
+        let can_seek = self.file.handle.can_seek();
+        let result = if can_seek {
+            self.file.read_xtc_at(start, self.natoms)
+        } else {
+            // Check the number of atoms before allocating space for them
+            let mut frame = XTCFrame::empty();
+            match self.natoms {
+                Some(natoms) => self.file.read_xtc_reuse(natoms, &mut frame),
+                None => self.file.read_xtc_next(&mut frame),
+            }.map(|()| frame)
+        };
+        let error = match result {
+            Ok(frame) => {
+                self.natoms = Some(frame.x.len());
+                return Some(Ok(Recovered::Frame(frame)))
+            }
+            Err(e) if e.is_eof() => {
+                self.done = true;
+                return None
+            }
+            Err(e) => e,
+        };
+        if !can_seek {
+            self.done = true;
+            return Some(Err(error))
+        }
+
+        self.file.frame = None;
+        let file_size = match self.file.handle.stream_len() {
+            Ok(file_size) => file_size,
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e.into()))
+            }
+        };
+        let found = match resync(&mut self.file.handle, TrajectoryFormat::Xtc, start + 1, file_size, self.natoms) {
+            Ok(Some(offset)) => self.file.read_xtc_at(offset, self.natoms).map(|frame| Some((offset, frame))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e),
+        };
+        let end = match found {
+            Ok(Some((offset, frame))) => {
+                self.natoms = Some(frame.x.len());
+                self.pending = Some(frame);
+                offset
+            }
+            Ok(None) => {
+                self.done = true;
+                file_size
+            }
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e))
+            }
+        };
+        Some(Ok(Recovered::Skipped(SkippedBytes { range: start..end, error })))
+    }
+}
+
+impl std::iter::FusedIterator for RecoverFrames<'_> {}
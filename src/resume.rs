@@ -0,0 +1,82 @@
+use std::{ffi::c_int, fs::OpenOptions, io::SeekFrom, path::Path};
+
+use crate::{
+    Error, XDRFile, XTCFrame, access_mode,
+    frames::read_header,
+    xdr::*,
+};
+
+/// What [`XDRFile::open_resume`] kept and removed from the end of the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResumeInfo {
+    /// Number of frames kept
+    pub frames: usize,
+    /// Number of complete frames removed because they came after `max_step`
+    pub frames_removed: usize,
+    /// Number of bytes removed, including any partially written frame
+    pub bytes_removed: u64,
+}
+
+impl XDRFile<access_mode::Append> {
+    /// Open an xtc file to carry on writing after a simulation was stopped, such as by a crash,
+    /// in the same way as restarting GROMACS from a checkpoint with `-cpi`.
+    ///
+    /// Every frame is read from the start of the file. Frames are kept up to the first frame
+    /// with a step greater than `max_step`, if it is given, which can be used to match the step
+    /// of the checkpoint. The file is truncated after the last frame kept, also removing a
+    /// partially written frame at the end, and opened for appending from there. The number of
+    /// frames kept and the data removed are returned with the file.
+    ///
+    /// Any other frame which can't be read, such as damaged data or a file which isn't in the
+    /// xtc format, is returned as an error without changing the file.
+    ///
+    /// If the last frame kept uses the GROMACS 2023 magic number, it is used for every frame
+    /// written, as set by [`XDRFile::set_xtc_2023`].
+    pub fn open_resume<P: AsRef<Path>>(path: P, max_step: Option<c_int>) -> Result<(Self, ResumeInfo), Error> {
+        let path = path.as_ref();
+        let mut xtc_read = XDRFile::<access_mode::Read>::open_path(path)?;
+        let file_size = xtc_read.handle.stream_len()?;
+        let mut frame = XTCFrame::empty();
+        let mut info = ResumeInfo { frames: 0, frames_removed: 0, bytes_removed: 0 };
+        // Start of the first frame removed
+        let mut end = None;
+        let mut xtc_2023 = false;
+        while xtc_read.handle.position() < file_size {
+            let offset = xtc_read.handle.position();
+            if let Err(e) = xtc_read.read_xtc_next(&mut frame) {
+                // Only a frame cut short by the end of the file can have been left part way
+                // through writing, and anything else would be lost by truncating
+                let partial = xtc_read.handle.seek(SeekFrom::Start(offset)) == XDRStatus::exdrOK
+                    && match read_header(&mut xtc_read.handle, Some(file_size)) {
+                        Ok(_) | Err(XDRStatus::exdrMAGIC) => false,
+                        Err(_) => xtc_read.handle.position() >= file_size,
+                    };
+                if !partial {
+                    return Err(e)
+                }
+                end.get_or_insert(offset);
+                break
+            }
+            if end.is_none() && max_step.is_some_and(|max_step| frame.step > max_step) {
+                end = Some(offset);
+            }
+            if end.is_some() {
+                info.frames_removed += 1;
+            } else {
+                info.frames += 1;
+                xtc_2023 = xtc_read.handle.xtc_2023;
+            }
+        }
+        drop(xtc_read);
+
+        let end = end.unwrap_or(file_size);
+        info.bytes_removed = file_size - end;
+        if info.bytes_removed > 0 {
+            OpenOptions::new().write(true).open(path)?.set_len(end)?;
+        }
+        let mut file = Self::open_path(path)?;
+        file.frame = Some(info.frames);
+        file.handle.force_xtc_2023 = xtc_2023;
+        Ok((file, info))
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    ffi::c_int,
+    ops::Range,
+};
+
+use crate::{
+    Error, ErrorKind, XDRAccessMode, XDRFile, XTCFrame, access_mode,
+    xdr::*,
+};
+
+/// A set of atoms to read from each frame of a trajectory, such as an index group.
+///
+/// Atoms are returned in the order they were given, and may be repeated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    atoms: Vec<usize>,
+    /// Pairs of atom index and position in `atoms`, sorted by atom index, so atoms can be
+    /// picked out in the order they are decompressed
+    order: Vec<(usize, usize)>,
+}
+
+impl Selection {
+    pub fn new(atoms: Vec<usize>) -> Self {
+        let mut order: Vec<_> = atoms.iter().enumerate().map(|(pos, &atom)| (atom, pos)).collect();
+        order.sort_unstable();
+        Self { atoms, order }
+    }
+
+    /// Indices of the selected atoms, counting from 0
+    pub fn atoms(&self) -> &[usize] {
+        &self.atoms
+    }
+
+    /// Number of atoms in the selection
+    pub fn len(&self) -> usize {
+        self.atoms.len()
+    }
+
+    /// Whether the selection contains no atoms
+    pub fn is_empty(&self) -> bool {
+        self.atoms.is_empty()
+    }
+
+    /// Largest selected atom index
+    pub fn max_atom(&self) -> Option<usize> {
+        self.order.last().map(|&(atom, _)| atom)
+    }
+}
+
+impl From<Vec<usize>> for Selection {
+    fn from(atoms: Vec<usize>) -> Self {
+        Self::new(atoms)
+    }
+}
+
+impl From<&[usize]> for Selection {
+    fn from(atoms: &[usize]) -> Self {
+        Self::new(atoms.to_vec())
+    }
+}
+
+impl From<Range<usize>> for Selection {
+    fn from(atoms: Range<usize>) -> Self {
+        // Already sorted, so skip the sort in `new`
+        Self {
+            order: atoms.clone().enumerate().map(|(pos, atom)| (atom, pos)).collect(),
+            atoms: atoms.collect(),
+        }
+    }
+}
+
+impl FromIterator<usize> for Selection {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+impl<MODE: XDRAccessMode + access_mode::Writable> XDRFile<MODE> {
+    /// Write a frame to an xtc file containing only the atoms of `x` in `selection`, in the
+    /// order of the selection.
+    /// Returns an error of kind `ErrorKind::AtomIndex` if the selection contains an atom index
+    /// which is out of range for `x`, in which case nothing is written.
+    pub fn write_xtc_subset(
+        &mut self,
+        step: i32,
+        time: f32,
+        sim_box: matrix,
+        x: &[rvec],
+        selection: &Selection,
+        prec: f32
+    ) -> Result<(), Error> {
+        if let Some(index) = selection.max_atom().filter(|&index| index >= x.len()) {
+            let offset = self.offset();
+            return Err(self.error_at(ErrorKind::AtomIndex { index, natoms: x.len() }, offset))
+        }
+        let subset: Vec<_> = selection.atoms().iter().map(|&i| x[i]).collect();
+        self.write_xtc(step, time, sim_box, &subset, prec)
+    }
+}
+
+impl XDRFile<access_mode::Read> {
+    /// Read the atoms in `selection` from the next frame of an xtc file, which must contain
+    /// `natoms` atoms. `frame.x` is resized to the size of the selection, and holds the atoms in
+    /// the same order as the selection.
+    ///
+    /// The whole frame still has to be decompressed, but only the selected atoms are stored, so
+    /// no buffer is needed for the full set of coordinates.
+    /// Returns an error of kind `ErrorKind::AtomIndex` if the selection contains an atom index
+    /// which is out of range, in which case nothing is read.
+    pub fn read_xtc_subset_reuse(&mut self, natoms: usize, selection: &Selection, frame: &mut XTCFrame) -> Result<(), Error> {
+        let offset = self.offset();
+        if let Some(index) = selection.max_atom().filter(|&index| index >= natoms) {
+            return Err(self.error_at(ErrorKind::AtomIndex { index, natoms }, offset))
+        }
+        let file_natoms = self.read_xtc_header_checked(natoms, &mut frame.step, &mut frame.time, offset)?;
+        if self.handle.read_float(frame.sim_box.0.as_flattened_mut()) != DIM * DIM {
+            return Err(self.error_at(XDRStatus::exdrFLOAT, offset))
+        }
+
+        frame.x.resize(selection.len(), rvec::new());
+        let x = &mut frame.x;
+        let mut order = selection.order.iter().peekable();
+        let mut ncoord: c_int = file_natoms;
+        let n = self.handle.decompress_coord_each(&mut ncoord, &mut frame.prec, natoms, |i, c| {
+            while let Some(&(_, pos)) = order.next_if(|&&(atom, _)| atom == i) {
+                x[pos] = rvec(c);
+            }
+        });
+        let result = if n == file_natoms { XDRStatus::exdrOK } else { XDRStatus::exdr3DX };
+        self.frame_result(result, offset)
+    }
+
+    /// Read the atoms in `selection` from the next frame of an xtc file.
+    /// See [`XDRFile::read_xtc_subset_reuse`].
+    pub fn read_xtc_subset(&mut self, natoms: usize, selection: &Selection) -> Result<XTCFrame, Error> {
+        let mut frame = XTCFrame::empty();
+        self.read_xtc_subset_reuse(natoms, selection, &mut frame)?;
+        Ok(frame)
+    }
+}
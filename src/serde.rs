@@ -0,0 +1,572 @@
+//! [serde](https://serde.rs) data format for the XDR representation described in RFC 4506.
+//!
+//! Values are big-endian and padded to a multiple of 4 bytes:
+//! - `bool`, `i8`, `i16`, `i32`, `u8`, `u16`, `u32` and `char` are stored as 4-byte
+//!   (unsigned) ints, and 64-bit integers as 8-byte hypers
+//! - `f32` and `f64` are stored as floats and doubles
+//! - strings, byte buffers, sequences and maps are prefixed by their length as an unsigned int
+//! - `Option` is stored as a bool followed by the value if it is present
+//! - tuples, arrays and structs are stored as their fields in order, with no length
+//! - enums are stored as the variant index as an unsigned int, followed by any fields
+//!
+//! Since the format isn't self-describing, `deserialize_any` and `deserialize_ignored_any` are
+//! not supported.
+
+use std::{
+    ffi::{c_int, c_uint},
+    fmt::Display,
+};
+
+use ::serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor},
+    ser::{self, Serialize},
+};
+
+use crate::{
+    Error, ErrorKind, MAX_PREALLOC, XDRAccessMode, XDRFile, access_mode,
+    xdr::XDRStatus,
+};
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::new(ErrorKind::Custom(msg.to_string()))
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::new(ErrorKind::Custom(msg.to_string()))
+    }
+}
+
+impl<MODE: XDRAccessMode + access_mode::Writable> XDRFile<MODE> {
+    /// Write any serializable value in the XDR representation. See the [`serde`](crate::serde)
+    /// module for how each type is stored.
+    pub fn serialize<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut Serializer::new(self))
+    }
+}
+
+impl XDRFile<access_mode::Read> {
+    /// Read a value written by [`XDRFile::serialize`]
+    pub fn deserialize<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
+        T::deserialize(&mut Deserializer::new(self))
+    }
+}
+
+/// Length prefix of a string, byte buffer, sequence or map
+fn length(len: usize) -> Result<c_uint, Error> {
+    c_uint::try_from(len).map_err(|_| ErrorKind::Overflow.into())
+}
+
+/// serde `Serializer` which writes to an [`XDRFile`]
+pub struct Serializer<'a, MODE: XDRAccessMode + access_mode::Writable> {
+    file: &'a mut XDRFile<MODE>,
+}
+
+impl<'a, MODE: XDRAccessMode + access_mode::Writable> Serializer<'a, MODE> {
+    pub fn new(file: &'a mut XDRFile<MODE>) -> Self {
+        Self { file }
+    }
+}
+
+impl<MODE: XDRAccessMode + access_mode::Writable> ser::Serializer for &mut Serializer<'_, MODE> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.file.write_int(&[v as c_int])
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.file.write_int(&[v.into()])
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.file.write_int(&[v.into()])
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.file.write_int(&[v])
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.file.write_uint(&[v.into()])
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.file.write_uint(&[v.into()])
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.file.write_uint(&[v])
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        // Most significant word first
+        self.file.write_uint(&[(v >> 32) as c_uint, v as c_uint])
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.file.write_float(&[v])
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        self.file.write_double(&[v])
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.file.write_uint(&[v.into()])
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        self.file.write_string(v, c_uint::MAX as usize)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        self.file.write_uint(&[length(v.len())?])?;
+        self.file.write_opaque(v)
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.serialize_bool(false)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        self.serialize_bool(true)?;
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, variant_index: u32, _variant: &'static str) -> Result<(), Error> {
+        self.serialize_u32(variant_index)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.serialize_u32(variant_index)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self, Error> {
+        let Some(len) = len else {
+            return Err(ser::Error::custom("xdr sequences must have a known length"))
+        };
+        self.file.write_uint(&[length(len)?])?;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, Error> {
+        self.serialize_u32(variant_index)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self, Error> {
+        let Some(len) = len else {
+            return Err(ser::Error::custom("xdr maps must have a known length"))
+        };
+        self.file.write_uint(&[length(len)?])?;
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, Error> {
+        self.serialize_u32(variant_index)?;
+        Ok(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+/// Implement the serde traits for compound types, which are all written as their elements in
+/// order
+macro_rules! serialize_elements {
+    ($($trait:ident::$method:ident),*) => {
+        $(
+            impl<MODE: XDRAccessMode + access_mode::Writable> ser::$trait for &mut Serializer<'_, MODE> {
+                type Ok = ();
+                type Error = Error;
+
+                fn $method<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+                    value.serialize(&mut **self)
+                }
+
+                fn end(self) -> Result<(), Error> {
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+serialize_elements!(
+    SerializeSeq::serialize_element,
+    SerializeTuple::serialize_element,
+    SerializeTupleStruct::serialize_field,
+    SerializeTupleVariant::serialize_field
+);
+
+impl<MODE: XDRAccessMode + access_mode::Writable> ser::SerializeMap for &mut Serializer<'_, MODE> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<MODE: XDRAccessMode + access_mode::Writable> ser::SerializeStruct for &mut Serializer<'_, MODE> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<MODE: XDRAccessMode + access_mode::Writable> ser::SerializeStructVariant for &mut Serializer<'_, MODE> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// serde `Deserializer` which reads from an [`XDRFile`]
+pub struct Deserializer<'a> {
+    file: &'a mut XDRFile<access_mode::Read>,
+}
+
+impl<'a> Deserializer<'a> {
+    pub fn new(file: &'a mut XDRFile<access_mode::Read>) -> Self {
+        Self { file }
+    }
+
+    fn read_int(&mut self) -> Result<c_int, Error> {
+        let mut v = [0];
+        self.file.read_int(&mut v)?;
+        Ok(v[0])
+    }
+
+    fn read_uint(&mut self) -> Result<c_uint, Error> {
+        let mut v = [0];
+        self.file.read_uint(&mut v)?;
+        Ok(v[0])
+    }
+
+    fn read_hyper(&mut self) -> Result<u64, Error> {
+        let mut v = [0; 2];
+        self.file.read_uint(&mut v)?;
+        Ok((v[0] as u64) << 32 | v[1] as u64)
+    }
+
+    fn read_len(&mut self) -> Result<usize, Error> {
+        Ok(self.read_uint()? as usize)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(de::Error::custom("xdr is not a self-describing format"))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.read_int()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            v => Err(de::Error::invalid_value(de::Unexpected::Signed(v.into()), &"0 or 1")),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.read_int()?;
+        visitor.visit_i8(v.try_into().map_err(|_| Error::from(ErrorKind::Overflow))?)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.read_int()?;
+        visitor.visit_i16(v.try_into().map_err(|_| Error::from(ErrorKind::Overflow))?)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i32(self.read_int()?)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i64(self.read_hyper()? as i64)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.read_uint()?;
+        visitor.visit_u8(v.try_into().map_err(|_| Error::from(ErrorKind::Overflow))?)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.read_uint()?;
+        visitor.visit_u16(v.try_into().map_err(|_| Error::from(ErrorKind::Overflow))?)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u32(self.read_uint()?)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u64(self.read_hyper()?)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let mut v = [0.];
+        self.file.read_float(&mut v)?;
+        visitor.visit_f32(v[0])
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let mut v = [0.];
+        self.file.read_double(&mut v)?;
+        visitor.visit_f64(v[0])
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let v = self.read_uint()?;
+        match char::from_u32(v) {
+            Some(c) => visitor.visit_char(c),
+            None => Err(de::Error::invalid_value(de::Unexpected::Unsigned(v.into()), &"a unicode scalar value")),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_string(self.file.read_string(c_uint::MAX as usize)?)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let len = self.read_len()?;
+        let offset = self.file.offset();
+        // The length comes from the data, so it is only allocated as far as there are bytes to read
+        let mut bytes = Vec::new();
+        if !self.file.handle.read_opaque_vec(&mut bytes, len) {
+            return Err(self.file.error_at(XDRStatus::exdrSTRING, offset))
+        }
+        visitor.visit_byte_buf(bytes)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.read_int()? {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            v => Err(de::Error::invalid_value(de::Unexpected::Signed(v.into()), &"0 or 1")),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let len = self.read_len()?;
+        visitor.visit_seq(Elements { de: self, len })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(Elements { de: self, len })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, len: usize, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(Elements { de: self, len })
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let len = self.read_len()?;
+        visitor.visit_map(Elements { de: self, len })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_seq(Elements { de: self, len: fields.len() })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        // Only used for enum variants, which are stored by index
+        self.deserialize_u32(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(de::Error::custom("xdr is not a self-describing format"))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+/// Access to the `len` elements of a sequence, tuple, struct or map
+struct Elements<'a, 'b> {
+    de: &'a mut Deserializer<'b>,
+    len: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for Elements<'_, '_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        if self.len == 0 {
+            return Ok(None)
+        }
+        self.len -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len.min(MAX_PREALLOC))
+    }
+}
+
+impl<'de> de::MapAccess<'de> for Elements<'_, '_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        if self.len == 0 {
+            return Ok(None)
+        }
+        self.len -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len.min(MAX_PREALLOC))
+    }
+}
+
+impl<'de> de::EnumAccess<'de> for &mut Deserializer<'_> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let index = self.read_uint()?;
+        let value = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(index))?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for &mut Deserializer<'_> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(Elements { de: self, len })
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(Elements { de: self, len: fields.len() })
+    }
+}
@@ -0,0 +1,240 @@
+use std::{
+    ffi::{CStr, c_int, c_float, c_double},
+    path::Path,
+};
+
+use crate::xdr::*;
+
+/// Magic number at the start of every trr frame
+pub const MAGIC: c_int = 1993;
+
+/// Version string written after the magic number
+const VERSION: &CStr = c"GMX_trn_file";
+
+/// Bit flags in `has_prop` indicating which properties were present in a frame read by [`read_trr`]
+pub const TRR_HAS_BOX: u8 = 1;
+pub const TRR_HAS_POSITIONS: u8 = 2;
+pub const TRR_HAS_VELOCITIES: u8 = 4;
+pub const TRR_HAS_FORCES: u8 = 8;
+
+/// Header of a trr frame. The `*_size` fields hold the number of bytes of each block of data
+/// following the header, or 0 if it is not present.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct TRRHeader {
+    pub ir_size: c_int,
+    pub e_size: c_int,
+    pub box_size: c_int,
+    pub vir_size: c_int,
+    pub pres_size: c_int,
+    pub top_size: c_int,
+    pub sym_size: c_int,
+    pub x_size: c_int,
+    pub v_size: c_int,
+    pub f_size: c_int,
+    pub natoms: c_int,
+    pub step: c_int,
+    pub nre: c_int,
+    pub t: c_float,
+    pub lambda: c_float,
+    /// Whether real numbers in the frame are stored in double precision
+    pub is_double: bool,
+}
+
+impl TRRHeader {
+    /// Work out the size of real numbers from the size of the data blocks
+    fn float_size(&self) -> Result<c_int, XDRStatus> {
+        let ncoord = self.natoms as i64 * DIM as i64;
+        let nflsz = if self.box_size != 0 {
+            self.box_size as i64 / (DIM * DIM) as i64
+        } else if ncoord > 0 && self.x_size != 0 {
+            self.x_size as i64 / ncoord
+        } else if ncoord > 0 && self.v_size != 0 {
+            self.v_size as i64 / ncoord
+        } else if ncoord > 0 && self.f_size != 0 {
+            self.f_size as i64 / ncoord
+        } else {
+            return Err(XDRStatus::exdrHEADER)
+        };
+        let nflsz = nflsz as c_int;
+        if nflsz != size_of::<c_float>() as c_int && nflsz != size_of::<c_double>() as c_int {
+            return Err(XDRStatus::exdrHEADER)
+        }
+        Ok(nflsz)
+    }
+}
+
+/// This function returns the number of atoms in the trr file in `natoms`.
+pub fn read_trr_natoms<P: AsRef<Path>>(fname: P, natoms: &mut c_int) -> XDRStatus {
+    let Ok(mut xd) = XDRFILE::open(fname, b'r' as _) else { return XDRStatus::exdrFILENOTFOUND };
+    let mut sh = TRRHeader::default();
+    let result = trr_header(&mut xd, &mut sh, true);
+    *natoms = sh.natoms;
+    result
+}
+
+/// Read one frame of an open trr file.
+/// `x`, `v` and `f` must each have space for at least the number of atoms in the frame.
+/// On return, `has_prop` holds a combination of the `TRR_HAS_*` flags indicating which
+/// properties were present in the frame.
+#[allow(clippy::too_many_arguments)]
+pub fn read_trr(
+    xd: &mut XDRFILE,
+    step: &mut c_int,
+    t: &mut c_float,
+    lambda: &mut c_float,
+    r#box: &mut matrix,
+    x: &mut [rvec],
+    v: &mut [rvec],
+    f: &mut [rvec],
+    has_prop: &mut u8
+) -> XDRStatus {
+    let mut sh = TRRHeader::default();
+    match trr_header(xd, &mut sh, true) {
+        XDRStatus::exdrOK => (),
+        e => return e,
+    }
+    *step = sh.step;
+    *t = sh.t;
+    *lambda = sh.lambda;
+    *has_prop = 0;
+    if sh.box_size != 0 { *has_prop |= TRR_HAS_BOX }
+    if sh.x_size != 0 { *has_prop |= TRR_HAS_POSITIONS }
+    if sh.v_size != 0 { *has_prop |= TRR_HAS_VELOCITIES }
+    if sh.f_size != 0 { *has_prop |= TRR_HAS_FORCES }
+    read_trr_data(xd, &sh, r#box, Some(x), Some(v), Some(f))
+}
+
+/// Write a frame to a trr file. Any of `box`, `x`, `v` and `f` may be omitted, but `x`, `v` and
+/// `f` must all have the same length if present.
+#[allow(clippy::too_many_arguments)]
+pub fn write_trr(
+    xd: &mut XDRFILE,
+    step: c_int,
+    t: c_float,
+    lambda: c_float,
+    r#box: Option<&matrix>,
+    x: Option<&[rvec]>,
+    v: Option<&[rvec]>,
+    f: Option<&[rvec]>
+) -> XDRStatus {
+    let mut lens = [x, v, f].into_iter().flatten().map(|p| p.len());
+    let len = lens.next().unwrap_or(0);
+    if lens.any(|l| l != len) {
+        return XDRStatus::exdr3DX
+    }
+    let Ok(natoms) = c_int::try_from(len) else { return XDRStatus::exdrUINT };
+    let Some(nbytes) = natoms.checked_mul((DIM * size_of::<c_float>()) as c_int) else {
+        return XDRStatus::exdrUINT
+    };
+    let size = |p: Option<&[rvec]>| if p.is_some() { nbytes } else { 0 };
+    let mut sh = TRRHeader {
+        box_size: if r#box.is_some() { (DIM * DIM * size_of::<c_float>()) as c_int } else { 0 },
+        x_size: size(x),
+        v_size: size(v),
+        f_size: size(f),
+        natoms,
+        step,
+        t,
+        lambda,
+        ..Default::default()
+    };
+    match trr_header(xd, &mut sh, false) {
+        XDRStatus::exdrOK => (),
+        e => return e,
+    }
+    if let Some(b) = r#box {
+        if xd.write_float(b.0.as_flattened()) != DIM * DIM { return XDRStatus::exdrFLOAT }
+    }
+    for p in [x, v, f].into_iter().flatten() {
+        if xd.write_float(flatten(p)) != p.len() * DIM { return XDRStatus::exdrFLOAT }
+    }
+    XDRStatus::exdrOK
+}
+
+/// Read or write the header of the current frame.
+/// When writing, the `is_double` field is ignored and single precision is used.
+#[allow(non_snake_case)]
+pub fn trr_header(xd: &mut XDRFILE, sh: &mut TRRHeader, bRead: bool) -> XDRStatus {
+    if bRead {
+        let mut magic = [0];
+        if xd.read_int(&mut magic) != 1 { return XDRStatus::exdrENDOFFILE }
+        if magic[0] != MAGIC { return XDRStatus::exdrMAGIC }
+        let mut slen = [0];
+        if xd.read_int(&mut slen) != 1 { return XDRStatus::exdrINT }
+        if slen[0] != VERSION.count_bytes() as c_int + 1 { return XDRStatus::exdrSTRING }
+        let mut buf = [0u8; 128];
+        if xd.read_string(&mut buf) == 0 { return XDRStatus::exdrSTRING }
+
+        let mut ints = [0; 13];
+        if xd.read_int(&mut ints) != ints.len() { return XDRStatus::exdrINT }
+        [
+            sh.ir_size, sh.e_size, sh.box_size, sh.vir_size, sh.pres_size, sh.top_size,
+            sh.sym_size, sh.x_size, sh.v_size, sh.f_size, sh.natoms, sh.step, sh.nre,
+        ] = ints;
+        sh.is_double = match sh.float_size() {
+            Ok(nflsz) => nflsz == size_of::<c_double>() as c_int,
+            Err(e) => return e,
+        };
+
+        let mut reals = [0 as c_float; 2];
+        if !read_real(xd, sh.is_double, &mut reals) {
+            return if sh.is_double { XDRStatus::exdrDOUBLE } else { XDRStatus::exdrFLOAT }
+        }
+        [sh.t, sh.lambda] = reals;
+    } else {
+        sh.is_double = false;
+        if xd.write_int(&[MAGIC, VERSION.count_bytes() as c_int + 1]) != 2 { return XDRStatus::exdrINT }
+        if xd.write_string(VERSION) != VERSION.count_bytes() + 1 { return XDRStatus::exdrSTRING }
+        let ints = [
+            sh.ir_size, sh.e_size, sh.box_size, sh.vir_size, sh.pres_size, sh.top_size,
+            sh.sym_size, sh.x_size, sh.v_size, sh.f_size, sh.natoms, sh.step, sh.nre,
+        ];
+        if xd.write_int(&ints) != ints.len() { return XDRStatus::exdrINT }
+        if xd.write_float(&[sh.t, sh.lambda]) != 2 { return XDRStatus::exdrFLOAT }
+    }
+    XDRStatus::exdrOK
+}
+
+/// Read the data following a frame header `sh`.
+/// Any of `x`, `v` or `f` that are present in the frame must be provided, with space for at
+/// least `sh.natoms` atoms. The virial and pressure are skipped if present. Frames with any of
+/// the input record, energy, topology or symmetry blocks, which GROMACS never writes, are
+/// rejected with `exdrHEADER`.
+pub fn read_trr_data(
+    xd: &mut XDRFILE,
+    sh: &TRRHeader,
+    r#box: &mut matrix,
+    x: Option<&mut [rvec]>,
+    v: Option<&mut [rvec]>,
+    f: Option<&mut [rvec]>
+) -> XDRStatus {
+    if [sh.ir_size, sh.e_size, sh.top_size, sh.sym_size].iter().any(|&size| size != 0) {
+        return XDRStatus::exdrHEADER
+    }
+    let err = if sh.is_double { XDRStatus::exdrDOUBLE } else { XDRStatus::exdrFLOAT };
+    if sh.box_size != 0 && !read_real(xd, sh.is_double, r#box.0.as_flattened_mut()) { return err }
+    let mut tmp = matrix::new();
+    for size in [sh.vir_size, sh.pres_size] {
+        if size != 0 && !read_real(xd, sh.is_double, tmp.0.as_flattened_mut()) { return err }
+    }
+    let Ok(natoms) = usize::try_from(sh.natoms) else { return XDRStatus::exdrHEADER };
+    for (size, p) in [(sh.x_size, x), (sh.v_size, v), (sh.f_size, f)] {
+        if size == 0 { continue }
+        let Some(p) = p.and_then(|p| p.get_mut(..natoms)) else { return XDRStatus::exdr3DX };
+        if !read_real(xd, sh.is_double, flatten_mut(p)) { return err }
+    }
+    XDRStatus::exdrOK
+}
+
+/// Read single or double precision reals into `data`
+fn read_real(xd: &mut XDRFILE, is_double: bool, data: &mut [c_float]) -> bool {
+    if !is_double {
+        return xd.read_float(data) == data.len()
+    }
+    let mut d = [0 as c_double];
+    for x in data {
+        if xd.read_double(&mut d) != 1 { return false }
+        *x = d[0] as c_float;
+    }
+    true
+}
@@ -1,13 +1,108 @@
-use std::ffi::{c_char, c_int, c_uint, c_uchar, c_short, c_ushort, c_float, c_double};
+use std::{
+    any::Any,
+    ffi::{CStr, c_char, c_int, c_uint, c_uchar, c_short, c_ushort, c_float, c_double},
+    fs::{File, Metadata, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Write, Seek, SeekFrom},
+    path::Path,
+};
 
-// NOTE: once `extern_types` feature is enabled, this could be `extern "C" type XDRFILE`
-#[repr(C)]
+/// Handle to a portable binary (XDR) file.
+///
+/// This is a native replacement for the `XDRFILE` type from libxdrfile. All data is stored
+/// big-endian and padded to multiples of 4 bytes, as described in RFC 4506.
+///
+/// As well as files on disk, any [`Read`] or [`Write`] implementor can be used as the
+/// underlying stream. Streams which can't seek, such as pipes, can still be read and written
+/// sequentially, but functions which need to jump around the file will fail.
 pub struct XDRFILE {
-    _data: [u8; 0],
-    _marker: core::marker::PhantomData<(*mut u8, core::marker::PhantomPinned)>,
+    stream: Stream,
+    /// Current byte position in the stream, which is tracked here so it is available for
+    /// streams that can't seek
+    pos: u64,
+    /// Scratch buffer holding the integer coordinates during compression
+    buf1: Vec<c_int>,
+    /// Scratch buffer holding the compressed bytes during (de)compression
+    buf2: Vec<u8>,
+    /// Whether the number of compressed bytes is stored as a 64-bit integer, as in xtc frames
+    /// with the 2023 magic number. Set by `xtc_header` for each frame.
+    pub(crate) xtc_2023: bool,
+    /// Whether to write the 2023 magic number for every xtc frame, rather than only for frames
+    /// too large for the original format
+    pub(crate) force_xtc_2023: bool,
+}
+
+enum Stream {
+    Read(BufReader<Box<dyn ReadBackend>>),
+    Write(BufWriter<Box<dyn WriteBackend>>),
+}
+
+/// Stream underlying an `XDRFILE`
+trait Backend: Seek {
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    fn metadata(&self) -> io::Result<Metadata> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "stream has no metadata"))
+    }
+
+    /// Unwrap the original stream so it can be downcast
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+trait ReadBackend: Read + Backend {}
+impl<T: Read + Backend> ReadBackend for T {}
+
+trait WriteBackend: Write + Backend {}
+impl<T: Write + Backend> WriteBackend for T {}
+
+impl Backend for File {
+    fn metadata(&self) -> io::Result<Metadata> {
+        File::metadata(self)
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+/// Wrapper for streams that can seek
+struct Seekable<T>(T);
+
+/// Wrapper for streams that can only be read or written in order
+struct Unseekable<T>(T);
+
+impl<T: Read> Read for Seekable<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { self.0.read(buf) }
+}
+impl<T: Write> Write for Seekable<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> { self.0.write(buf) }
+    fn flush(&mut self) -> io::Result<()> { self.0.flush() }
+}
+impl<T: Seek> Seek for Seekable<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> { self.0.seek(pos) }
+}
+impl<T: Seek + 'static> Backend for Seekable<T> {
+    fn into_any(self: Box<Self>) -> Box<dyn Any> { Box::new(self.0) }
+}
+
+impl<T: Read> Read for Unseekable<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { self.0.read(buf) }
+}
+impl<T: Write> Write for Unseekable<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> { self.0.write(buf) }
+    fn flush(&mut self) -> io::Result<()> { self.0.flush() }
+}
+impl<T> Seek for Unseekable<T> {
+    fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "stream can't seek"))
+    }
+}
+impl<T: 'static> Backend for Unseekable<T> {
+    fn can_seek(&self) -> bool { false }
+    fn into_any(self: Box<Self>) -> Box<dyn Any> { Box::new(self.0) }
 }
 
-#[repr(C)]
 #[allow(non_camel_case_types)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum XDRStatus {
@@ -40,6 +135,54 @@ impl rvec {
     }
 }
 
+impl Default for rvec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// View a slice of `rvec`s as a flat slice of `3*x.len()` floats
+pub(crate) fn flatten(x: &[rvec]) -> &[c_float] {
+    // SAFETY: rvec is a repr(C) wrapper around [c_float; DIM], so has the same layout
+    unsafe { std::slice::from_raw_parts(x.as_ptr() as *const c_float, x.len() * DIM) }
+}
+
+/// View a mutable slice of `rvec`s as a flat slice of `3*x.len()` floats
+pub(crate) fn flatten_mut(x: &mut [rvec]) -> &mut [c_float] {
+    // SAFETY: rvec is a repr(C) wrapper around [c_float; DIM], so has the same layout
+    unsafe { std::slice::from_raw_parts_mut(x.as_mut_ptr() as *mut c_float, x.len() * DIM) }
+}
+
+/// Double precision vector, for coordinates kept as `f64`
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct dvec(pub [c_double; DIM]);
+
+impl dvec {
+    pub fn new() -> Self {
+        dvec([0 as c_double; DIM])
+    }
+}
+
+impl Default for dvec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// View a slice of `dvec`s as a flat slice of `3*x.len()` doubles
+pub(crate) fn flatten_double(x: &[dvec]) -> &[c_double] {
+    // SAFETY: dvec is a repr(C) wrapper around [c_double; DIM], so has the same layout
+    unsafe { std::slice::from_raw_parts(x.as_ptr() as *const c_double, x.len() * DIM) }
+}
+
+/// View a mutable slice of `dvec`s as a flat slice of `3*x.len()` doubles
+pub(crate) fn flatten_double_mut(x: &mut [dvec]) -> &mut [c_double] {
+    // SAFETY: dvec is a repr(C) wrapper around [c_double; DIM], so has the same layout
+    unsafe { std::slice::from_raw_parts_mut(x.as_mut_ptr() as *mut c_double, x.len() * DIM) }
+}
+
 #[allow(non_camel_case_types)]
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -51,301 +194,1014 @@ impl matrix {
     }
 }
 
-#[allow(non_camel_case_types)]
-#[repr(C)]
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub enum mybool {FALSE, TRUE}
+impl Default for matrix {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-#[link(name="xdrfile")]
-extern "C" {
+/// Generate a pair of functions for reading/writing arrays of a 4 or 8 byte XDR type
+macro_rules! xdr_rw {
+    ($read:ident, $write:ident, $t:ty, $nbytes:literal, $from:expr, $to:expr, $name:literal) => {
+        #[doc = concat!("Read one or more `", stringify!($t), "` type variable(s)")]
+        #[doc = ""]
+        #[doc = concat!("`ptr` is the memory where data should be written. `ptr.len()` ", $name, " are read.")]
+        #[doc = concat!("Returns the number of ", $name, " read")]
+        pub fn $read(&mut self, ptr: &mut [$t]) -> usize {
+            let mut buf = [0u8; $nbytes];
+            for (i, v) in ptr.iter_mut().enumerate() {
+                if !self.read_bytes(&mut buf) { return i }
+                *v = $from(buf);
+            }
+            ptr.len()
+        }
 
+        #[doc = concat!("Write one or more `", stringify!($t), "` type variable(s)")]
+        #[doc = ""]
+        #[doc = concat!("`ptr` is the memory where the data should be read. `ptr.len()` ", $name, " are written.")]
+        #[doc = concat!("Returns the number of ", $name, " written")]
+        pub fn $write(&mut self, ptr: &[$t]) -> usize {
+            for (i, v) in ptr.iter().enumerate() {
+                if !self.write_bytes(&$to(*v)) { return i }
+            }
+            ptr.len()
+        }
+    };
+}
+
+impl XDRFILE {
     /// Open a portable binary file, just like C fopen()
-    /// `mode` should be `"r"` for reading, `"w"` for writing, or `"a"` for appending.
-    /// Returns a `NULL` pointer if an error occurred
-    pub fn xdrfile_open(path: *const c_char, mode: *const c_char) -> *mut XDRFILE;
+    /// `mode` should be `'r'` for reading, `'w'` for writing, or `'a'` for appending.
+    pub fn open<P: AsRef<Path>>(path: P, mode: c_char) -> io::Result<Self> {
+        match mode as u8 {
+            b'r' => Ok(Self::new(Stream::Read(BufReader::new(Box::new(File::open(path)?))), 0)),
+            b'w' => Ok(Self::new(Stream::Write(BufWriter::new(Box::new(File::create(path)?))), 0)),
+            b'a' => {
+                let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+                let pos = file.seek(SeekFrom::End(0))?;
+                Ok(Self::new(Stream::Write(BufWriter::new(Box::new(file))), pos))
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid xdr file mode")),
+        }
+    }
+
+    /// Read from a seekable stream, starting at its current position
+    pub fn from_reader<R: Read + Seek + 'static>(mut reader: R) -> io::Result<Self> {
+        let pos = reader.stream_position()?;
+        Ok(Self::new(Stream::Read(BufReader::new(Box::new(Seekable(reader)))), pos))
+    }
+
+    /// Read from a stream that can't seek, such as a pipe
+    pub fn from_unseekable_reader<R: Read + 'static>(reader: R) -> Self {
+        Self::new(Stream::Read(BufReader::new(Box::new(Unseekable(reader)))), 0)
+    }
+
+    /// Write to a seekable stream, starting at its current position
+    pub fn from_writer<W: Write + Seek + 'static>(mut writer: W) -> io::Result<Self> {
+        let pos = writer.stream_position()?;
+        Ok(Self::new(Stream::Write(BufWriter::new(Box::new(Seekable(writer)))), pos))
+    }
+
+    /// Write to a stream that can't seek, such as a pipe
+    pub fn from_unseekable_writer<W: Write + 'static>(writer: W) -> Self {
+        Self::new(Stream::Write(BufWriter::new(Box::new(Unseekable(writer)))), 0)
+    }
+
+    fn new(stream: Stream, pos: u64) -> Self {
+        Self {
+            stream,
+            pos,
+            buf1: Vec::new(),
+            buf2: Vec::new(),
+            xtc_2023: false,
+            force_xtc_2023: false,
+        }
+    }
+
+    /// Flush any buffered data and return the underlying stream, which must be of type `T`.
+    /// This is a [`File`] if the `XDRFILE` was opened from a path, or the stream that was passed
+    /// in otherwise. Any data buffered for reading is lost.
+    pub fn into_inner<T: 'static>(self) -> io::Result<T> {
+        let backend: Box<dyn Any> = match self.stream {
+            Stream::Read(r) => r.into_inner().into_any(),
+            Stream::Write(w) => w.into_inner().map_err(|e| e.into_error())?.into_any(),
+        };
+        match backend.downcast() {
+            Ok(inner) => Ok(*inner),
+            Err(_) => Err(io::Error::new(io::ErrorKind::InvalidInput, "stream is not of the requested type")),
+        }
+    }
 
     /// Close a previously opened portable binary file, just like C fclose()
     /// Returns 0 on success, non-zero on error
-    pub fn xdrfile_close(xfp: *mut XDRFILE) -> c_int;
-
-    /// Read one or more `c_char` type variable(s)
-    /// `ptr` is a pointer to memory where data should be written.
-    /// `ndata` is the number of characters to read.
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of characters read
-    pub fn xdrfile_read_char(ptr: *mut c_char, ndata: c_int, xfp: *mut XDRFILE) -> c_int;
-
-    /// Write one or more `c_char` type variable(s)
-    /// `ptr` is a pointer to memory where the data should be read
-    /// `ndata` is the number of characters to write
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of characters written
-    pub fn xdrfile_write_char(ptr: *mut c_char, ndata: c_int, xfp: *mut XDRFILE) -> c_int;
-
-    /// Read one or more `c_uchar` type variable(s)
-    /// `ptr` is a pointer to memory where data should be written.
-    /// `ndata` is the number of characters to read.
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of unsigned characters read
-    pub fn xdrfile_read_uchar(ptr: *mut c_uchar, ndata: c_int, xfp: *mut XDRFILE) -> c_int;
-
-    /// Write one or more `c_uchar` type variable(s)
-    /// `ptr` is a pointer to memory where the data should be read
-    /// `ndata` is the number of characters to write
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of unsigned characters written
-    pub fn xdrfile_write_uchar(ptr: *mut c_uchar, ndata: c_int, xfp: *mut XDRFILE) -> c_int;
-
-    /// Read one or more `c_short` type variable(s)
-    /// `ptr` is a pointer to memory where data should be written.
-    /// `ndata` is the number of shorts to read.
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of shorts read
-    pub fn xdrfile_read_short(ptr: *mut c_short, ndata: c_int, xfp: *mut XDRFILE) -> c_int;
-
-    /// Write one or more `c_short` type variable(s)
-    /// `ptr` is a pointer to memory where the data should be read
-    /// `ndata` is the number of shorts to write
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of shorts written
-    pub fn xdrfile_write_short(ptr: *mut c_short, ndata: c_int, xfp: *mut XDRFILE) -> c_int;
-
-    /// Read one or more `c_ushort` type variable(s)
-    /// `ptr` is a pointer to memory where data should be written.
-    /// `ndata` is the number of unsigned shorts to read.
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of unsigned shorts read
-    pub fn xdrfile_read_ushort(ptr: *mut c_ushort, ndata: c_int, xfp: *mut XDRFILE) -> c_int;
-
-    /// Write one or more `c_ushort` type variable(s)
-    /// `ptr` is a pointer to memory where the data should be read
-    /// `ndata` is the number of unsigned shorts to write
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of unsigned shorts written
-    pub fn xdrfile_write_ushort(ptr: *mut c_ushort, ndata: c_int, xfp: *mut XDRFILE) -> c_int;
-
-    /// Read one or more `c_int` type variable(s)
-    /// `ptr` is a pointer to memory where data should be written.
-    /// `ndata` is the number of ints to read.
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of integers read
-    ///
-    /// NOTE: No routines are provided for reading/writing 64-bit integers, since:
-    ///     - Not all XDR implementations support it
-    ///     - Not all machines have 64-bit integers
-    pub fn xdrfile_read_int(ptr: *mut c_int, ndata: c_int, xfp: *mut XDRFILE) -> c_int;
-
-    /// Write one or more `c_int` type variable(s)
-    /// `ptr` is a pointer to memory where the data should be read
-    /// `ndata` is the number of ints to write
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of integers written
-    ///
-    /// NOTE: No routines are provided for reading/writing 64-bit integers, since:
-    ///     - Not all XDR implementations support it
-    ///     - Not all machines have 64-bit integers
-    pub fn xdrfile_write_int(ptr: *mut c_int, ndata: c_int, xfp: *mut XDRFILE) -> c_int;
-
-    /// Read one or more `c_uint` type variable(s)
-    /// `ptr` is a pointer to memory where data should be written.
-    /// `ndata` is the number of unsigned ints to read.
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of unsigned integers read
-    ///
-    /// NOTE: No routines are provided for reading/writing 64-bit integers, since:
-    ///     - Not all XDR implementations support it
-    ///     - Not all machines have 64-bit integers
-    pub fn xdrfile_read_uint(ptr: *mut c_uint, ndata: c_int, xfp: *mut XDRFILE) -> c_int;
-
-    /// Write one or more `c_uint` type variable(s)
-    /// `ptr` is a pointer to memory where the data should be read
-    /// `ndata` is the number of unsigned ints to write
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of unsigned integers written
-    ///
-    /// NOTE: No routines are provided for reading/writing 64-bit integers, since:
-    ///     - Not all XDR implementations support it
-    ///     - Not all machines have 64-bit integers
-    pub fn xdrfile_write_uint(ptr: *mut c_uint, ndata: c_int, xfp: *mut XDRFILE) -> c_int;
-
-    /// Read one or more `c_float` type variable(s)
-    /// `ptr` is a pointer to memory where data should be written.
-    /// `ndata` is the number of floats to read.
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of floats read
-    pub fn xdrfile_read_float(ptr: *mut c_float, ndata: c_int, xfp: *mut XDRFILE) -> c_int;
-
-    /// Write one or more `c_float` type variable(s)
-    /// `ptr` is a pointer to memory where the data should be read
-    /// `ndata` is the number of floats to write
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of floats written
-    pub fn xdrfile_write_float(ptr: *mut c_float, ndata: c_int, xfp: *mut XDRFILE) -> c_int;
-
-    /// Read one or more `c_double` type variable(s)
-    /// `ptr` is a pointer to memory where data should be written.
-    /// `ndata` is the number of doubles to read.
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of floats read
-    pub fn xdrfile_read_double(ptr: *mut c_double, ndata: c_int, xfp: *mut XDRFILE) -> c_int;
-
-    /// Write one or more `c_doubles` type variable(s)
-    /// `ptr` is a pointer to memory where the data should be read
-    /// `ndata` is the number of doubles to write
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of doubles written
-    pub fn xdrfile_write_double(ptr: *mut c_double, ndata: c_int, xfp: *mut XDRFILE) -> c_int;
+    pub fn close(mut self) -> c_int {
+        match self.flush() {
+            Ok(()) => 0,
+            Err(_) => XDRStatus::exdrCLOSE as c_int,
+        }
+    }
+
+    /// Query metadata of the underlying file.
+    /// Fails with [`io::ErrorKind::Unsupported`] if the stream isn't a file.
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        match &self.stream {
+            Stream::Read(r) => r.get_ref().metadata(),
+            Stream::Write(w) => w.get_ref().metadata(),
+        }
+    }
+
+    /// Length of the underlying stream in bytes, found by seeking to its end and back, so it
+    /// works for any seekable stream, and includes data appended since it was opened.
+    /// Buffered data is kept, and the position is unchanged.
+    pub fn stream_len(&mut self) -> io::Result<u64> {
+        fn len<S: Seek + ?Sized>(s: &mut S) -> io::Result<u64> {
+            let pos = s.stream_position()?;
+            let end = s.seek(SeekFrom::End(0))?;
+            if pos != end {
+                s.seek(SeekFrom::Start(pos))?;
+            }
+            Ok(end)
+        }
+        match &mut self.stream {
+            Stream::Read(r) => len(r.get_mut()),
+            Stream::Write(w) => {
+                w.flush()?;
+                len(w.get_mut())
+            }
+        }
+    }
+
+    /// Whether the underlying stream supports seeking to arbitrary positions
+    pub fn can_seek(&self) -> bool {
+        match &self.stream {
+            Stream::Read(r) => r.get_ref().can_seek(),
+            Stream::Write(w) => w.get_ref().can_seek(),
+        }
+    }
+
+    /// Flush any buffered data to the underlying file
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.stream {
+            Stream::Read(_) => Ok(()),
+            Stream::Write(w) => w.flush(),
+        }
+    }
+
+    // NOTE: These count the bytes transferred even if they fail part way, so the position
+    // stays correct for streams that can't seek
+    fn read_bytes(&mut self, buf: &mut [u8]) -> bool {
+        let Stream::Read(r) = &mut self.stream else { return false };
+        let mut n = 0;
+        while n < buf.len() {
+            match r.read(&mut buf[n..]) {
+                Ok(0) => break,
+                Ok(k) => n += k,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(_) => break,
+            }
+        }
+        self.pos += n as u64;
+        n == buf.len()
+    }
+
+    fn write_bytes(&mut self, buf: &[u8]) -> bool {
+        let Stream::Write(w) = &mut self.stream else { return false };
+        let mut n = 0;
+        while n < buf.len() {
+            match w.write(&buf[n..]) {
+                Ok(0) => break,
+                Ok(k) => n += k,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(_) => break,
+            }
+        }
+        self.pos += n as u64;
+        n == buf.len()
+    }
+
+    // NOTE: XDR stores chars and shorts as full 4 byte integers
+    xdr_rw!(read_char, write_char, c_char, 4,
+        |b| i32::from_be_bytes(b) as c_char, |v: c_char| (v as i32).to_be_bytes(), "characters");
+    xdr_rw!(read_uchar, write_uchar, c_uchar, 4,
+        |b| u32::from_be_bytes(b) as c_uchar, |v: c_uchar| (v as u32).to_be_bytes(), "unsigned characters");
+    xdr_rw!(read_short, write_short, c_short, 4,
+        |b| i32::from_be_bytes(b) as c_short, |v: c_short| (v as i32).to_be_bytes(), "shorts");
+    xdr_rw!(read_ushort, write_ushort, c_ushort, 4,
+        |b| u32::from_be_bytes(b) as c_ushort, |v: c_ushort| (v as u32).to_be_bytes(), "unsigned shorts");
+    xdr_rw!(read_int, write_int, c_int, 4,
+        c_int::from_be_bytes, c_int::to_be_bytes, "integers");
+    xdr_rw!(read_uint, write_uint, c_uint, 4,
+        c_uint::from_be_bytes, c_uint::to_be_bytes, "unsigned integers");
+    xdr_rw!(read_float, write_float, c_float, 4,
+        c_float::from_be_bytes, c_float::to_be_bytes, "floats");
+    xdr_rw!(read_double, write_double, c_double, 8,
+        c_double::from_be_bytes, c_double::to_be_bytes, "doubles");
 
     /// Read a C style string (array of `c_char`)
-    /// `ptr` is a pointer to memory where data should be written.
-    /// `maxlen` is the maximum length of the string. If no end-of-string is encountered,
-    /// one byte less than this is read and end-of-string is appended.
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of characters read, including end-of-string.
-    pub fn xdrfile_read_string(ptr: *mut c_char, maxlen: c_int, xfp: *mut XDRFILE) -> c_int;
+    /// `ptr` is the memory where data should be written. The string, including end-of-string,
+    /// must fit in `ptr`.
+    /// Returns the number of characters read, including end-of-string, or 0 on error.
+    pub fn read_string(&mut self, ptr: &mut [u8]) -> usize {
+        let mut len = [0 as c_uint];
+        if self.read_uint(&mut len) != 1 { return 0 }
+        let len = len[0] as usize;
+        if len >= ptr.len() { return 0 }
+        if self.read_opaque(&mut ptr[..len]) != len { return 0 }
+        ptr[len] = 0;
+        ptr[..len].iter().position(|&c| c == 0).unwrap_or(len) + 1
+    }
 
     /// Write a C style string (array of characters)
-    /// `ptr` is a pointer to memory where the data should be read
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of characters written, including end-of-string
-    pub fn xdrfile_write_string(ptr: *mut c_char, xfp: *mut XDRFILE) -> c_int;
+    /// Returns the number of characters written, including end-of-string, or 0 on error.
+    pub fn write_string(&mut self, ptr: &CStr) -> usize {
+        let bytes = ptr.to_bytes();
+        let Ok(len) = c_uint::try_from(bytes.len()) else { return 0 };
+        if self.write_uint(&[len]) != 1 { return 0 }
+        if self.write_opaque(bytes) != bytes.len() { return 0 }
+        bytes.len() + 1
+    }
 
     /// Read raw bytes from the file (unknown datatype)
-    /// `ptr` is a pointer to memory where data should be written.
-    /// `nbytes` is the number of bytes to read. No conversion whatsoever is done.
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
+    /// `ptr` is the memory where data should be written. `ptr.len()` bytes are read, and
+    /// the file is then advanced past any padding to the next multiple of 4 bytes.
+    /// No conversion whatsoever is done.
     /// Returns the number of bytes read
-    pub fn xdrfile_read_opaque(ptr: *mut c_char, nbytes: c_int, xfp: *mut XDRFILE) -> c_int;
+    pub fn read_opaque(&mut self, ptr: &mut [u8]) -> usize {
+        if !self.read_bytes(ptr) { return 0 }
+        let mut pad = [0u8; 4];
+        let npad = (4 - ptr.len() % 4) % 4;
+        if !self.read_bytes(&mut pad[..npad]) { return 0 }
+        ptr.len()
+    }
+
+    /// Read `n` raw bytes into `buf`, as for `read_opaque`, growing it as the data is read so
+    /// that a size from a damaged file isn't allocated before it is found to be too large
+    pub(crate) fn read_opaque_vec(&mut self, buf: &mut Vec<u8>, n: usize) -> bool {
+        const CHUNK: usize = 1 << 20;
+        buf.clear();
+        while buf.len() < n {
+            let start = buf.len();
+            buf.resize(n.min(start + CHUNK), 0);
+            if !self.read_bytes(&mut buf[start..]) { return false }
+        }
+        let npad = (4 - n % 4) % 4;
+        self.read_bytes(&mut [0u8; 4][..npad])
+    }
 
     /// Write raw bytes to the file (unknown datatype)
-    /// `ptr` is a pointer to memory where the data should be read
-    /// `nbytes` is the number of bytes to write. No conversion whatsoever is done.
-    /// `xfp` is a handle to the portable binary file created with `xdrfile_open()`
-    /// Returns the number of doubles written
-    pub fn xdrfile_write_opaque(ptr: *mut c_char, nbytes: c_int, xfp: *mut XDRFILE) -> c_int;
-
-	/// Compress coordiates in a `c_float` array to XDR file
-	///
-	/// This routine will perform *lossy* compression on the three-dimensional
-	/// coordinate data data specified and store it in the XDR file.
-	///
-	/// The lossy part of the compression consists of multiplying each
-	/// coordinate with the precision argument and then rounding to integers.
-	/// We suggest a default value of 1000.0, which means you are guaranteed
-	/// three decimals of accuracy. The only limitation is that scaled coordinates
-	/// must still fit in an integer variable, so if the precision is 1000.0 the
-	/// coordinate magnitudes must be less than +-2e6.
-	///
-	/// `ptr` is a pointer to coordinates to compress (length 3*ncoord)
-	/// `ncoord` is the number of coordinate triplets in data
-	/// `precision` is the scaling factor for lossy compression. If it is <=0,
-	/// the default value of 1000.0 is used.
-	/// `xfp` is a handle to a portably binary file
-	///
-	/// Returns the number of coordinate triplets written.
-	/// IMPORTANT: Check that this is equal to ncoord - if it is
-	///            negative, an error occured. This should not happen with
-	///    	       normal data, but if your coordinates are NaN or very
-	///            large (>1e6) it is not possible to use the compression.
-	///
-	/// WARNING: The compression algorithm is not part of the XDR standard,
-	///          and very complicated, so you will need this xdrfile module
-	///          to read it later.
-    pub fn xdrfile_compress_coord_float(ptr: *mut c_float, ncoord: c_int, precision: c_float, xfp: *mut XDRFILE) -> c_int;
-
-	/// Decompress coordiates from XDR file to array of `c_float`s
-	///
-	/// This routine will decompress three-dimensional coordinate data previously
-	/// stored in an XDR file and store it in the specified array of floats.
-	///
-	/// The precision used during the earlier compression is read from the file
-	/// and returned - you cannot adjust the accuracy at this stage.
-	///
-	/// `ptr` is a pointer to memory where the decompressed coordinates will be stored (length>= 3*ncoord)
-	/// `ncoord` is the maximum number of coordinate triplets to read on input, and is modified to
+    /// `ptr` is the memory where the data should be read. The data is padded with zeros to a
+    /// multiple of 4 bytes. No conversion whatsoever is done.
+    /// Returns the number of bytes written
+    pub fn write_opaque(&mut self, ptr: &[u8]) -> usize {
+        if !self.write_bytes(ptr) { return 0 }
+        let npad = (4 - ptr.len() % 4) % 4;
+        if !self.write_bytes(&[0u8; 4][..npad]) { return 0 }
+        ptr.len()
+    }
+
+    /// Compress coordiates in a `c_float` array to XDR file
+    ///
+    /// This routine will perform *lossy* compression on the three-dimensional
+    /// coordinate data data specified and store it in the XDR file.
+    ///
+    /// The lossy part of the compression consists of multiplying each
+    /// coordinate with the precision argument and then rounding to integers.
+    /// We suggest a default value of 1000.0, which means you are guaranteed
+    /// three decimals of accuracy. The only limitation is that scaled coordinates
+    /// must still fit in an integer variable, so if the precision is 1000.0 the
+    /// coordinate magnitudes must be less than +-2e6.
+    ///
+    /// `ptr` is the coordinates to compress (length 3*ncoord)
+    /// `precision` is the scaling factor for lossy compression. If it is <=0,
+    /// the default value of 1000.0 is used.
+    ///
+    /// Returns the number of coordinate triplets written.
+    /// IMPORTANT: Check that this is equal to ncoord - if it is
+    ///            negative, an error occured. This should not happen with
+    ///               normal data, but if your coordinates are NaN or very
+    ///            large (>1e6) it is not possible to use the compression.
+    ///
+    /// The number of compressed bytes is stored as a 64-bit integer in xtc frames with the 2023
+    /// magic number, as set by [`xtc_header`](crate::xtc::xtc_header).
+    ///
+    /// WARNING: The compression algorithm is not part of the XDR standard,
+    ///          and very complicated, so you will need this xdrfile module
+    ///          to read it later.
+    pub fn compress_coord_float(&mut self, ptr: &[c_float], precision: c_float) -> c_int {
+        self.compress_coord(ptr, precision)
+    }
+
+    /// Decompress coordiates from XDR file to array of `c_float`s
+    ///
+    /// This routine will decompress three-dimensional coordinate data previously
+    /// stored in an XDR file and store it in the specified array of floats.
+    ///
+    /// The precision used during the earlier compression is read from the file
+    /// and returned - you cannot adjust the accuracy at this stage.
+    ///
+    /// `ptr` is the memory where the decompressed coordinates will be stored (length>= 3*ncoord)
+    /// `ncoord` is the maximum number of coordinate triplets to read on input, and is modified to
     /// the actual number of coordinate triplets read on return. If this is smaller than the number
     /// of coordinates in the frame an error will occur.
     ///
-	/// The precision used in the previous compression will be written to `precision` on return.
+    /// The precision used in the previous compression will be written to `precision` on return.
+    /// Frames of 9 atoms or less are stored uncompressed, in which case `precision` is left
+    /// untouched.
+    ///
+    /// Returns the number of coordinate triplets read. If this is negative, an error occured.
+    pub fn decompress_coord_float(&mut self, ptr: &mut [c_float], ncoord: &mut c_int, precision: &mut c_float) -> c_int {
+        self.decompress_coord(ptr, ncoord, precision)
+    }
+
+    /// Compress coordiates in a `c_double` array to XDR file
     ///
-	/// `xfp` is a handle to a portable binary file
-	///
-	/// Returns the number of coordinate triplets read. If this is negative, an error occured.
-	///
-	/// WARNING: Since we cannot count on being able to set/get the
-	///          position of large files (>2Gb), it is not possible to
-	///          recover from errors by re-reading the frame if the
-	///          storage area you provided was too small. To avoid this
-	///          from happening, we recommend that you store the number of
-	///          coordinates triplet as an integer either in a header or
-	///          just before the compressed coordinate data, so you can
-	///          read it first and allocated enough memory.
-	///
-    pub fn xdrfile_decompress_coord_float(ptr: *mut c_float, ncoord: *mut c_int, precision: *mut c_float, xfp: *mut XDRFILE) -> c_int;
-
-	/// Compress coordiates in a `c_double` array to XDR file
-	///
-	/// This routine will perform *lossy* compression on the three-dimensional
-	/// coordinate data data specified and store it in the XDR file. This will
+    /// This routine will perform *lossy* compression on the three-dimensional
+    /// coordinate data data specified and store it in the XDR file. This will
     /// NOT give you any extra precision since the coordinates are compressed.
     /// This routine just avoids allocating a temporary array of `c_float`s.
-	///
-	/// The lossy part of the compression consists of multiplying each
-	/// coordinate with the precision argument and then rounding to integers.
-	/// We suggest a default value of 1000.0, which means you are guaranteed
-	/// three decimals of accuracy. The only limitation is that scaled coordinates
-	/// must still fit in an integer variable, so if the precision is 1000.0 the
-	/// coordinate magnitudes must be less than +-2e6.
-	///
-	/// `ptr` is a pointer to coordinates to compress (length 3*ncoord)
-	/// `ncoord` is the number of coordinate triplets in data
-	/// `precision` is the scaling factor for lossy compression. If it is <=0,
-	/// the default value of 1000.0 is used.
-	/// `xfp` is a handle to a portably binary file
-	///
-	/// Returns the number of coordinate triplets written.
-	/// IMPORTANT: Check that this is equal to ncoord - if it is
-	///            negative, an error occured. This should not happen with
-	///    	       normal data, but if your coordinates are NaN or very
-	///            large (>1e6) it is not possible to use the compression.
-	///
-	/// WARNING: The compression algorithm is not part of the XDR standard,
-	///          and very complicated, so you will need this xdrfile module
-	///          to read it later.
-    pub fn xdrfile_compress_coord_double(ptr: *mut c_double, ncoord: c_int, precision: c_double, xfp: *mut XDRFILE) -> c_int;
-
-	/// Decompress coordiates from XDR file to array of `c_float`s. This will
+    ///
+    /// See [`XDRFILE::compress_coord_float`] for details on the arguments and return value.
+    pub fn compress_coord_double(&mut self, ptr: &[c_double], precision: c_double) -> c_int {
+        self.compress_coord(ptr, precision)
+    }
+
+    /// Decompress coordiates from XDR file to array of `c_double`s. This will
     /// NOT give you any extra precision since the coordinates are compressed.
     /// This routine just avoids allocating a temporary array of `c_float`s.
-	///
-	/// This routine will decompress three-dimensional coordinate data previously
-	/// stored in an XDR file and store it in the specified array of floats.
-	///
-	/// The precision used during the earlier compression is read from the file
-	/// and returned - you cannot adjust the accuracy at this stage.
-	///
-	/// `ptr` is a pointer to memory where the decompressed coordinates will be stored (length>= 3*ncoord)
-	/// `ncoord` is the maximum number of coordinate triplets to read on input, and is modified to
-    /// the actual number of coordinate triplets read on return. If this is smaller than the number
-    /// of coordinates in the frame an error will occur.
     ///
-	/// The precision used in the previous compression will be written to `precision` on return.
-    ///
-	/// `xfp` is a handle to a portable binary file
-	///
-	/// Returns the number of coordinate triplets read. If this is negative, an error occured.
-	///
-	/// WARNING: Since we cannot count on being able to set/get the
-	///          position of large files (>2Gb), it is not possible to
-	///          recover from errors by re-reading the frame if the
-	///          storage area you provided was too small. To avoid this
-	///          from happening, we recommend that you store the number of
-	///          coordinates triplet as an integer either in a header or
-	///          just before the compressed coordinate data, so you can
-	///          read it first and allocated enough memory.
-	///
-    pub fn xdrfile_decompress_coord_double(ptr: *mut c_double, ncoord: *mut c_int, precision: *mut c_double, xfp: *mut XDRFILE) -> c_int;
-
-    pub fn xdr_tell(xd: *mut XDRFILE) -> i64;
-    pub fn xdr_seek(xd: *mut XDRFILE, pos: i64, whence: c_int) -> XDRStatus;
+    /// See [`XDRFILE::decompress_coord_float`] for details on the arguments and return value.
+    pub fn decompress_coord_double(&mut self, ptr: &mut [c_double], ncoord: &mut c_int, precision: &mut c_double) -> c_int {
+        self.decompress_coord(ptr, ncoord, precision)
+    }
+
+    /// Get the current position in the file, or -1 on error
+    pub fn tell(&mut self) -> i64 {
+        i64::try_from(self.pos).unwrap_or(-1)
+    }
+
+    /// Current byte offset in the file.
+    /// Unlike [`XDRFILE::tell`], this can't fail, so it is used for frame offsets.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Move to a position in the file, just like C fseek()
+    /// Streams that can't seek can only be moved forwards when reading, by discarding data.
+    /// Offsets are always 64-bit, so files larger than 2 GB are supported on every platform.
+    pub fn seek(&mut self, pos: SeekFrom) -> XDRStatus {
+        let target = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+            SeekFrom::End(_) => None,
+        };
+        let result = match (&mut self.stream, pos) {
+            // Keep the read buffer when skipping short distances
+            (Stream::Read(r), SeekFrom::Current(offset)) => r.seek_relative(offset).map(|_| target),
+            (Stream::Read(r), pos) => r.seek(pos).map(Some),
+            (Stream::Write(w), pos) => w.seek(pos).map(Some),
+        };
+        match (result, &mut self.stream, target) {
+            (Ok(Some(new_pos)), _, _) => {
+                self.pos = new_pos;
+                XDRStatus::exdrOK
+            }
+            // Skip forwards through streams that can't seek
+            (Err(e), Stream::Read(r), Some(target))
+                if e.kind() == io::ErrorKind::Unsupported && target >= self.pos =>
+            {
+                let n = target - self.pos;
+                let skipped = io::copy(&mut r.by_ref().take(n), &mut io::sink()).unwrap_or(0);
+                self.pos += skipped;
+                if skipped == n { XDRStatus::exdrOK } else { XDRStatus::exdrENDOFFILE }
+            }
+            _ => XDRStatus::exdrNR,
+        }
+    }
+
+    fn compress_coord<T: Real>(&mut self, ptr: &[T], precision: T) -> c_int {
+        let Ok(size) = c_int::try_from(ptr.len() / DIM) else { return -1 };
+        let size3 = size as usize * DIM;
+        if self.write_int(&[size]) == 0 {
+            return -1
+        }
+
+        // Dont bother with compression for three atoms or less
+        if size <= 9 {
+            let mut fbuf = [0 as c_float; 9 * DIM];
+            for (f, x) in fbuf.iter_mut().zip(ptr) {
+                *f = x.to_float();
+            }
+            return (self.write_float(&fbuf[..size3]) / DIM) as c_int
+        }
+
+        // Compression-time if we got here. Write precision first
+        let precision = if precision.to_float() <= 0. { T::from_float(1000.) } else { precision };
+
+        let mut buf1 = std::mem::take(&mut self.buf1);
+        buf1.clear();
+        buf1.reserve(size3);
+        let mut minint = [c_int::MAX; DIM];
+        let mut maxint = [c_int::MIN; DIM];
+        let mut mindiff = c_int::MAX;
+        let mut oldlint = [0 as c_int; DIM];
+        let mut overflow = false;
+        for (i, x) in ptr[..size3].chunks_exact(DIM).enumerate() {
+            let mut diff: c_int = 0;
+            for d in 0..DIM {
+                // find nearest integer
+                let Some(lint) = x[d].to_scaled_int(precision) else {
+                    // scaling would cause overflow
+                    overflow = true;
+                    break;
+                };
+                minint[d] = minint[d].min(lint);
+                maxint[d] = maxint[d].max(lint);
+                buf1.push(lint);
+                diff = diff.wrapping_add(oldlint[d].wrapping_sub(lint).wrapping_abs());
+                oldlint[d] = lint;
+            }
+            if diff < mindiff && i > 0 {
+                mindiff = diff;
+            }
+        }
+        // turning value in unsigned by subtracting minint would cause overflow
+        if overflow || (0..DIM).any(|d| maxint[d] as c_float - minint[d] as c_float >= MAXABS) {
+            self.buf1 = buf1;
+            return -1;
+        }
+
+        self.write_float(&[precision.to_float()]);
+        self.write_int(&minint);
+        self.write_int(&maxint);
+
+        let mut sizeint = [0 as c_uint; DIM];
+        for d in 0..DIM {
+            sizeint[d] = (maxint[d] as c_uint).wrapping_sub(minint[d] as c_uint).wrapping_add(1);
+        }
+        let mut bitsizeint = [0 as c_uint; DIM];
+        // check if one of the sizes is to big to be multiplied
+        let bitsize = if (sizeint[0] | sizeint[1] | sizeint[2]) > 0xffffff {
+            for d in 0..DIM {
+                bitsizeint[d] = sizeofint(sizeint[d]);
+            }
+            0 // flag the use of large sizes
+        } else {
+            sizeofints(&sizeint)
+        };
+
+        let mut smallidx = FIRSTIDX;
+        while smallidx < LASTIDX && MAGICINTS[smallidx] < mindiff {
+            smallidx += 1;
+        }
+        self.write_int(&[smallidx as c_int]);
+
+        let maxidx = LASTIDX.min(smallidx + 8);
+        let minidx = maxidx - 8; // often this equal smallidx
+        let mut smaller = MAGICINTS[FIRSTIDX.max(smallidx - 1)] / 2;
+        let mut smallnum = MAGICINTS[smallidx] / 2;
+        let mut sizesmall = [MAGICINTS[smallidx] as c_uint; DIM];
+        let larger = MAGICINTS[maxidx] / 2;
+
+        let mut buf2 = std::mem::take(&mut self.buf2);
+        buf2.clear();
+        let mut bits = BitWriter::new(&mut buf2);
+        let mut prevcoord = [0 as c_int; DIM];
+        let mut tmpcoord = [0 as c_uint; 8 * DIM];
+        let mut prevrun = -1;
+        let size = size as usize;
+        let mut i = 0;
+        while i < size {
+            let mut is_small = false;
+            let this = i * DIM;
+            let close_to = |a: &[c_int], b: &[c_int], lim: c_int| {
+                (0..DIM).all(|d| a[d].wrapping_sub(b[d]).wrapping_abs() < lim)
+            };
+            let mut is_smaller = if smallidx < maxidx && i >= 1 && close_to(&buf1[this..], &prevcoord, larger) {
+                1
+            } else if smallidx > minidx {
+                -1
+            } else {
+                0
+            };
+            if i + 1 < size && close_to(&buf1[this..], &buf1[this + DIM..], smallnum) {
+                // interchange first with second atom for better
+                // compression of water molecules
+                for d in 0..DIM {
+                    buf1.swap(this + d, this + DIM + d);
+                }
+                is_small = true;
+            }
+            for d in 0..DIM {
+                tmpcoord[d] = buf1[this + d].wrapping_sub(minint[d]) as c_uint;
+            }
+            if bitsize == 0 {
+                for d in 0..DIM {
+                    bits.encodebits(bitsizeint[d], tmpcoord[d]);
+                }
+            } else if !bits.encodeints(bitsize, &sizeint, &tmpcoord[..DIM]) {
+                self.buf1 = buf1;
+                self.buf2 = buf2;
+                return -1;
+            }
+            prevcoord.copy_from_slice(&buf1[this..this + DIM]);
+            i += 1;
+
+            let mut run = 0;
+            if !is_small && is_smaller == -1 {
+                is_smaller = 0;
+            }
+            while is_small && run < 8 * DIM {
+                let this = i * DIM;
+                let mut tmpsum: c_int = 0;
+                for d in 0..DIM {
+                    let tmp = buf1[this + d].wrapping_sub(prevcoord[d]);
+                    tmpsum = tmpsum.wrapping_add(tmp.wrapping_mul(tmp));
+                }
+                if is_smaller == -1 && tmpsum >= smaller.wrapping_mul(smaller) {
+                    is_smaller = 0;
+                }
+                for d in 0..DIM {
+                    tmpcoord[run] = buf1[this + d].wrapping_sub(prevcoord[d]).wrapping_add(smallnum) as c_uint;
+                    run += 1;
+                }
+                prevcoord.copy_from_slice(&buf1[this..this + DIM]);
+                i += 1;
+                is_small = i < size && close_to(&buf1[i * DIM..], &prevcoord, smallnum);
+            }
+            if run as i32 != prevrun || is_smaller != 0 {
+                prevrun = run as i32;
+                bits.encodebits(1, 1); // flag the change in run-length
+                bits.encodebits(5, (run as i32 + is_smaller + 1) as c_uint);
+            } else {
+                bits.encodebits(1, 0); // flag the fact that runlength did not change
+            }
+            for k in (0..run).step_by(DIM) {
+                if !bits.encodeints(smallidx as c_uint, &sizesmall, &tmpcoord[k..k + DIM]) {
+                    self.buf1 = buf1;
+                    self.buf2 = buf2;
+                    return -1;
+                }
+            }
+            if is_smaller != 0 {
+                smallidx = (smallidx as i32 + is_smaller) as usize;
+                if is_smaller < 0 {
+                    smallnum = smaller;
+                    smaller = MAGICINTS[smallidx - 1] / 2;
+                } else {
+                    smaller = smallnum;
+                    smallnum = MAGICINTS[smallidx] / 2;
+                }
+                sizesmall = [MAGICINTS[smallidx] as c_uint; DIM];
+            }
+        }
+        bits.finish();
+
+        let wrote_nbytes = if self.xtc_2023 {
+            let nbytes = buf2.len() as u64;
+            self.write_uint(&[(nbytes >> 32) as c_uint, nbytes as c_uint]) == 2
+        } else {
+            c_int::try_from(buf2.len()).is_ok_and(|nbytes| self.write_int(&[nbytes]) == 1)
+        };
+        let result = if wrote_nbytes && self.write_opaque(&buf2) == buf2.len() { size as c_int } else { -1 };
+        self.buf1 = buf1;
+        self.buf2 = buf2;
+        result
+    }
+
+    /// Read the number of bytes of compressed coordinates, which is a 64-bit integer in xtc
+    /// frames with the 2023 magic number
+    pub(crate) fn read_nbytes(&mut self) -> Option<u64> {
+        if self.xtc_2023 {
+            let mut n = [0 as c_uint; 2];
+            if self.read_uint(&mut n) != 2 { return None }
+            Some((n[0] as u64) << 32 | n[1] as u64)
+        } else {
+            let mut n = [0 as c_int];
+            if self.read_int(&mut n) != 1 { return None }
+            u64::try_from(n[0]).ok()
+        }
+    }
+
+    fn decompress_coord<T: Real>(&mut self, ptr: &mut [T], ncoord: &mut c_int, precision: &mut T) -> c_int {
+        let max_atoms = ptr.len() / DIM;
+        self.decompress_coord_each(ncoord, precision, max_atoms, |i, c| {
+            ptr[i * DIM..(i + 1) * DIM].copy_from_slice(&c);
+        })
+    }
+
+    /// Decompress coordinates, passing the index and position of each atom to `emit` in order,
+    /// rather than storing them all. Fails without reading the coordinates if the frame contains
+    /// more than `max_atoms` atoms.
+    /// Returns the number of atoms decompressed, or -1 on error, as for `decompress_coord_float`.
+    pub(crate) fn decompress_coord_each<T: Real>(
+        &mut self,
+        ncoord: &mut c_int,
+        precision: &mut T,
+        max_atoms: usize,
+        mut emit: impl FnMut(usize, [T; DIM])
+    ) -> c_int {
+        let mut lsize = [0 as c_int];
+        if self.read_int(&mut lsize) == 0 {
+            return -1 // return if we could not read size
+        }
+        let lsize = lsize[0];
+        if lsize < 0 || *ncoord < lsize || max_atoms < lsize as usize {
+            return -1
+        }
+        *ncoord = lsize;
+        let size3 = lsize as usize * DIM;
+
+        // Dont bother with compression for three atoms or less
+        if lsize <= 9 {
+            let mut fbuf = [0 as c_float; 9 * DIM];
+            let nread = self.read_float(&mut fbuf[..size3]);
+            for (i, f) in fbuf[..nread].chunks_exact(DIM).enumerate() {
+                emit(i, [T::from_float(f[0]), T::from_float(f[1]), T::from_float(f[2])]);
+            }
+            // return number of coords, not floats
+            return (nread / DIM) as c_int
+        }
+
+        // Compression-time if we got here. Read precision first
+        let mut prec = [0 as c_float];
+        let mut minint = [0 as c_int; DIM];
+        let mut maxint = [0 as c_int; DIM];
+        let mut smallidx = [0 as c_int];
+        if self.read_float(&mut prec) != 1
+            || self.read_int(&mut minint) != DIM
+            || self.read_int(&mut maxint) != DIM
+            || self.read_int(&mut smallidx) != 1
+        {
+            return -1
+        }
+        let Some(nbytes) = self.read_nbytes() else { return -1 };
+        *precision = T::from_float(prec[0]);
+        let Ok(mut smallidx) = usize::try_from(smallidx[0]) else { return -1 };
+        let Ok(nbytes) = usize::try_from(nbytes) else { return -1 };
+        if !(FIRSTIDX..=LASTIDX).contains(&smallidx) {
+            return -1
+        }
+        // Compressed coordinates never take more than the 1.2 ints per coordinate that
+        // libxdrfile allocates for them, so a larger size comes from a damaged file and mustn't
+        // be allocated
+        if nbytes > size3 * size_of::<c_int>() * 6 / 5 {
+            return -1
+        }
+
+        let mut sizeint = [0 as c_uint; DIM];
+        for d in 0..DIM {
+            sizeint[d] = (maxint[d] as c_uint).wrapping_sub(minint[d] as c_uint).wrapping_add(1);
+        }
+        let mut bitsizeint = [0 as c_uint; DIM];
+        // check if one of the sizes is to big to be multiplied
+        let bitsize = if (sizeint[0] | sizeint[1] | sizeint[2]) > 0xffffff {
+            for d in 0..DIM {
+                bitsizeint[d] = sizeofint(sizeint[d]);
+            }
+            0 // flag the use of large sizes
+        } else {
+            sizeofints(&sizeint)
+        };
+
+        let mut smaller = MAGICINTS[FIRSTIDX.max(smallidx - 1)] / 2;
+        let mut smallnum = MAGICINTS[smallidx] / 2;
+        let mut sizesmall = [MAGICINTS[smallidx] as c_uint; DIM];
+
+        let mut buf2 = std::mem::take(&mut self.buf2);
+        let result = if !self.read_opaque_vec(&mut buf2, nbytes) {
+            None
+        } else {
+            let inv_precision = T::inverse(*precision);
+            let mut bits = BitReader::new(&buf2);
+            let mut natoms = 0;
+            let mut emit = |c: &[c_int; DIM]| {
+                if natoms >= lsize as usize { return None }
+                emit(natoms, c.map(|v| T::from_scaled_int(v, inv_precision)));
+                natoms += 1;
+                Some(())
+            };
+            let mut decode = || -> Option<()> {
+                let mut run = 0;
+                let mut i = 0;
+                while i < lsize {
+                    let mut thiscoord = [0 as c_int; DIM];
+                    if bitsize == 0 {
+                        for d in 0..DIM {
+                            thiscoord[d] = bits.decodebits(bitsizeint[d])? as c_int;
+                        }
+                    } else {
+                        bits.decodeints(bitsize, &sizeint, &mut thiscoord)?;
+                    }
+                    i += 1;
+                    for d in 0..DIM {
+                        thiscoord[d] = thiscoord[d].wrapping_add(minint[d]);
+                    }
+                    let mut prevcoord = thiscoord;
+
+                    let mut is_smaller = 0;
+                    if bits.decodebits(1)? == 1 {
+                        run = bits.decodebits(5)? as c_int;
+                        is_smaller = run % 3;
+                        run -= is_smaller;
+                        is_smaller -= 1;
+                    }
+                    if run > 0 {
+                        for k in (0..run).step_by(DIM) {
+                            let mut coord = [0 as c_int; DIM];
+                            bits.decodeints(smallidx as c_uint, &sizesmall, &mut coord)?;
+                            i += 1;
+                            for d in 0..DIM {
+                                coord[d] = coord[d].wrapping_add(prevcoord[d].wrapping_sub(smallnum));
+                            }
+                            if k == 0 {
+                                // interchange first with second atom for better
+                                // compression of water molecules
+                                std::mem::swap(&mut coord, &mut prevcoord);
+                                emit(&prevcoord)?;
+                            } else {
+                                prevcoord = coord;
+                            }
+                            emit(&coord)?;
+                        }
+                    } else {
+                        emit(&thiscoord)?;
+                    }
+
+                    smallidx = usize::try_from(smallidx as c_int + is_smaller).ok()?;
+                    if is_smaller < 0 {
+                        smallnum = smaller;
+                        smaller = if smallidx > FIRSTIDX { MAGICINTS[smallidx - 1] / 2 } else { 0 };
+                    } else if is_smaller > 0 {
+                        smaller = smallnum;
+                        smallnum = *MAGICINTS.get(smallidx)? / 2;
+                    }
+                    sizesmall = [*MAGICINTS.get(smallidx)? as c_uint; DIM];
+                    if sizesmall[0] == 0 {
+                        return None
+                    }
+                }
+                Some(())
+            };
+            decode()
+        };
+        self.buf2 = buf2;
+
+        match result {
+            Some(()) => lsize,
+            None => -1,
+        }
+    }
+}
+
+/// Floating point types which coordinates can be (de)compressed from/to
+pub(crate) trait Real: Copy {
+    fn to_float(self) -> c_float;
+    fn from_float(x: c_float) -> Self;
+    /// Multiply by `precision` and round to the nearest integer, or `None` if this would overflow
+    fn to_scaled_int(self, precision: Self) -> Option<c_int>;
+    fn from_scaled_int(x: c_int, inv_precision: Self) -> Self;
+    fn inverse(precision: Self) -> Self;
+}
+
+impl Real for c_float {
+    fn to_float(self) -> c_float { self }
+    fn from_float(x: c_float) -> Self { x }
+    fn to_scaled_int(self, precision: Self) -> Option<c_int> {
+        // NOTE: rounding is done in double precision to match libxdrfile
+        let scaled = (self * precision) as c_double;
+        let lf = if self >= 0. { scaled + 0.5 } else { scaled - 0.5 } as c_float;
+        if lf.is_nan() || lf.abs() > MAXABS { return None }
+        Some(lf as c_int)
+    }
+    fn from_scaled_int(x: c_int, inv_precision: Self) -> Self { x as c_float * inv_precision }
+    fn inverse(precision: Self) -> Self { (1.0 / precision as c_double) as c_float }
+}
+
+impl Real for c_double {
+    fn to_float(self) -> c_float { self as c_float }
+    fn from_float(x: c_float) -> Self { x as c_double }
+    fn to_scaled_int(self, precision: Self) -> Option<c_int> {
+        let lf = if self >= 0. { self * precision + 0.5 } else { self * precision - 0.5 };
+        if lf.is_nan() || lf.abs() > MAXABS as c_double { return None }
+        Some(lf as c_int)
+    }
+    fn from_scaled_int(x: c_int, inv_precision: Self) -> Self { x as c_double * inv_precision }
+    fn inverse(precision: Self) -> Self { 1.0 / precision }
+}
+
+/// Lookup table for the number of bits needed to store small differences between coordinates
+const MAGICINTS: [c_int; 73] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0,
+    8, 10, 12, 16, 20, 25, 32, 40, 50, 64,
+    80, 101, 128, 161, 203, 256, 322, 406, 512, 645,
+    812, 1024, 1290, 1625, 2048, 2580, 3250, 4096, 5060, 6501,
+    8192, 10321, 13003, 16384, 20642, 26007, 32768, 41285, 52015, 65536,
+    82570, 104031, 131072, 165140, 208063, 262144, 330280, 416127, 524287, 660561,
+    832255, 1048576, 1321122, 1664510, 2097152, 2642245, 3329021, 4194304, 5284491, 6658042,
+    8388607, 10568983, 13316085, 16777216,
+];
+
+const FIRSTIDX: usize = 9;
+// NOTE: libxdrfile uses the length of MAGICINTS here, which can index past the end of the
+// table for very sparse coordinates. Stop one short so the table lookups stay in bounds.
+const LASTIDX: usize = MAGICINTS.len() - 1;
+
+/// Largest magnitude of a scaled coordinate
+const MAXABS: c_float = (c_int::MAX - 2) as c_float;
+
+/// Number of bits needed to store an integer no larger than `size`
+fn sizeofint(size: c_uint) -> c_uint {
+    let mut num: c_uint = 1;
+    let mut num_of_bits = 0;
+    while size >= num && num_of_bits < 32 {
+        num_of_bits += 1;
+        num = num.wrapping_shl(1);
+    }
+    num_of_bits
+}
+
+/// Number of bits needed to store a set of integers with maximum values `sizes`
+fn sizeofints(sizes: &[c_uint; DIM]) -> c_uint {
+    let mut bytes = [0 as c_uint; 32];
+    let mut num_of_bytes = 1;
+    bytes[0] = 1;
+    for &size in sizes {
+        let mut tmp: c_uint = 0;
+        let mut bytecnt = 0;
+        while bytecnt < num_of_bytes {
+            tmp = bytes[bytecnt].wrapping_mul(size).wrapping_add(tmp);
+            bytes[bytecnt] = tmp & 0xff;
+            tmp >>= 8;
+            bytecnt += 1;
+        }
+        while tmp != 0 {
+            bytes[bytecnt] = tmp & 0xff;
+            bytecnt += 1;
+            tmp >>= 8;
+        }
+        num_of_bytes = bytecnt;
+    }
+    let mut num = 1;
+    let mut num_of_bits = 0;
+    num_of_bytes -= 1;
+    while bytes[num_of_bytes] >= num {
+        num_of_bits += 1;
+        num *= 2;
+    }
+    num_of_bits + num_of_bytes as c_uint * 8
+}
+
+/// Packs integers into a bit stream, most significant bit first
+struct BitWriter<'a> {
+    buf: &'a mut Vec<u8>,
+    lastbits: c_uint,
+    lastbyte: c_uint,
+}
+
+impl<'a> BitWriter<'a> {
+    fn new(buf: &'a mut Vec<u8>) -> Self {
+        Self { buf, lastbits: 0, lastbyte: 0 }
+    }
+
+    /// Write the lowest `num_of_bits` bits of `num`
+    fn encodebits(&mut self, mut num_of_bits: c_uint, num: c_uint) {
+        while num_of_bits >= 8 {
+            self.lastbyte = (self.lastbyte << 8) | (num >> (num_of_bits - 8));
+            self.buf.push((self.lastbyte >> self.lastbits) as u8);
+            num_of_bits -= 8;
+        }
+        if num_of_bits > 0 {
+            self.lastbyte = (self.lastbyte << num_of_bits) | (num & ((1 << num_of_bits) - 1));
+            self.lastbits += num_of_bits;
+            if self.lastbits >= 8 {
+                self.lastbits -= 8;
+                self.buf.push((self.lastbyte >> self.lastbits) as u8);
+            }
+        }
+    }
+
+    /// Write a set of integers `nums` with maximum values `sizes` into `num_of_bits` bits.
+    /// Returns `false` if one of the integers is too large for its size.
+    fn encodeints(&mut self, num_of_bits: c_uint, sizes: &[c_uint; DIM], nums: &[c_uint]) -> bool {
+        let mut bytes = [0 as c_uint; 32];
+        let mut num_of_bytes = 0;
+        let mut tmp = nums[0];
+        loop {
+            bytes[num_of_bytes] = tmp & 0xff;
+            num_of_bytes += 1;
+            tmp >>= 8;
+            if tmp == 0 { break }
+        }
+        for i in 1..DIM {
+            if nums[i] >= sizes[i] {
+                return false
+            }
+            // use one step multiply
+            tmp = nums[i];
+            let mut bytecnt = 0;
+            while bytecnt < num_of_bytes {
+                tmp = bytes[bytecnt].wrapping_mul(sizes[i]).wrapping_add(tmp);
+                bytes[bytecnt] = tmp & 0xff;
+                tmp >>= 8;
+                bytecnt += 1;
+            }
+            while tmp != 0 {
+                bytes[bytecnt] = tmp & 0xff;
+                bytecnt += 1;
+                tmp >>= 8;
+            }
+            num_of_bytes = bytecnt;
+        }
+        let nbits = num_of_bytes as c_uint * 8;
+        if num_of_bits >= nbits {
+            for &b in &bytes[..num_of_bytes] {
+                self.encodebits(8, b);
+            }
+            self.encodebits(num_of_bits - nbits, 0);
+        } else {
+            for &b in &bytes[..num_of_bytes - 1] {
+                self.encodebits(8, b);
+            }
+            self.encodebits(num_of_bits + 8 - nbits, bytes[num_of_bytes - 1]);
+        }
+        true
+    }
+
+    /// Flush any remaining bits, padding the last byte with zeros
+    fn finish(self) {
+        if self.lastbits > 0 {
+            self.buf.push((self.lastbyte << (8 - self.lastbits)) as u8);
+        }
+    }
+}
+
+/// Unpacks integers from a bit stream written by [`BitWriter`].
+/// Reading past the end of the stream returns `None`.
+struct BitReader<'a> {
+    buf: &'a [u8],
+    cnt: usize,
+    lastbits: c_uint,
+    lastbyte: c_uint,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, cnt: 0, lastbits: 0, lastbyte: 0 }
+    }
+
+    fn next_byte(&mut self) -> Option<c_uint> {
+        let b = *self.buf.get(self.cnt)?;
+        self.cnt += 1;
+        Some(b as c_uint)
+    }
+
+    /// Read an integer stored in `num_of_bits` bits
+    fn decodebits(&mut self, mut num_of_bits: c_uint) -> Option<c_uint> {
+        let mask = if num_of_bits >= 32 { c_uint::MAX } else { (1 << num_of_bits) - 1 };
+        let mut num: c_uint = 0;
+        while num_of_bits >= 8 {
+            self.lastbyte = (self.lastbyte << 8) | self.next_byte()?;
+            num |= (self.lastbyte >> self.lastbits) << (num_of_bits - 8);
+            num_of_bits -= 8;
+        }
+        if num_of_bits > 0 {
+            if self.lastbits < num_of_bits {
+                self.lastbits += 8;
+                self.lastbyte = (self.lastbyte << 8) | self.next_byte()?;
+            }
+            self.lastbits -= num_of_bits;
+            num |= (self.lastbyte >> self.lastbits) & ((1 << num_of_bits) - 1);
+        }
+        Some(num & mask)
+    }
+
+    /// Read a set of integers with maximum values `sizes` stored in `num_of_bits` bits
+    fn decodeints(&mut self, mut num_of_bits: c_uint, sizes: &[c_uint; DIM], nums: &mut [c_int; DIM]) -> Option<()> {
+        let mut bytes = [0 as c_uint; 32];
+        let mut num_of_bytes = 0;
+        while num_of_bits > 8 {
+            *bytes.get_mut(num_of_bytes)? = self.decodebits(8)?;
+            num_of_bytes += 1;
+            num_of_bits -= 8;
+        }
+        if num_of_bits > 0 {
+            *bytes.get_mut(num_of_bytes)? = self.decodebits(num_of_bits)?;
+            num_of_bytes += 1;
+        }
+        for i in (1..DIM).rev() {
+            if sizes[i] == 0 {
+                return None
+            }
+            let mut num: c_uint = 0;
+            for j in (0..num_of_bytes).rev() {
+                num = (num << 8) | bytes[j];
+                let p = num / sizes[i];
+                bytes[j] = p;
+                num -= p * sizes[i];
+            }
+            nums[i] = num as c_int;
+        }
+        nums[0] = (bytes[0] | (bytes[1] << 8) | (bytes[2] << 16) | (bytes[3] << 24)) as c_int;
+        Some(())
+    }
 }
@@ -1,19 +1,181 @@
-use std::ffi::{c_char, c_int, c_float};
+use std::{
+    ffi::{c_int, c_float, c_double},
+    io::SeekFrom,
+    path::Path,
+};
 
 use crate::xdr::*;
 
-#[link(name="xdrfile")]
-extern "C" {
-    /// This function returns the number of atoms in the xtc file in `natoms`.
-    pub fn read_xtc_natoms(fname: *const c_char, natoms: *mut c_int) -> XDRStatus;
-    
-    /// Read one frame of an open xtc file
-    pub fn read_xtc(xd: *mut XDRFILE, natoms: c_int, step: *mut c_int, time: *mut c_float, r#box: *mut [c_float; 3], x: *mut rvec, prec: *mut c_float) -> XDRStatus;
+/// Magic number at the start of every xtc frame
+pub const MAGIC: c_int = 1995;
 
-    /// Write a frame to an xtc file
-    // NOTE: C impl uses *mut for box and x, but doesn't mutate them through this call path.
-    pub fn write_xtc(xd: *mut XDRFILE, natoms: c_int, step: c_int, time: c_float, r#box: *const [c_float; 3], x: *const rvec, prec: c_float) -> XDRStatus;
+/// Magic number introduced in GROMACS 2023 for frames whose compressed coordinates may not fit
+/// in the original format, which differs only in storing the number of compressed bytes as a
+/// 64-bit integer
+pub const MAGIC_2023: c_int = 2023;
 
-    /// Read header information of the current frame
-    pub fn xtc_header(xd: *mut XDRFILE, natoms: *mut c_int, step: *mut c_int, time: *mut c_float, bRead: mybool) -> XDRStatus;
+/// Largest number of atoms written with the original magic number. Larger frames use
+/// [`MAGIC_2023`], as in GROMACS.
+pub const MAX_NATOMS_1995: c_int = 298261617;
+
+/// Whether `magic` is one of the xtc magic numbers
+pub fn is_magic(magic: c_int) -> bool {
+    magic == MAGIC || magic == MAGIC_2023
+}
+
+/// This function returns the number of atoms in the xtc file in `natoms`.
+pub fn read_xtc_natoms<P: AsRef<Path>>(fname: P, natoms: &mut c_int) -> XDRStatus {
+    let Ok(mut xd) = XDRFILE::open(fname, b'r' as _) else { return XDRStatus::exdrFILENOTFOUND };
+    let mut step = 0;
+    let mut time = 0.;
+    xtc_header(&mut xd, natoms, &mut step, &mut time, true)
+}
+
+/// Read one frame of an open xtc file.
+/// `x` must have space for at least the number of atoms in the frame.
+pub fn read_xtc(xd: &mut XDRFILE, step: &mut c_int, time: &mut c_float, r#box: &mut matrix, x: &mut [rvec], prec: &mut c_float) -> XDRStatus {
+    let mut natoms = 0;
+    match xtc_header(xd, &mut natoms, step, time, true) {
+        XDRStatus::exdrOK => (),
+        e => return e,
+    }
+    if natoms < 0 || natoms as usize > x.len() {
+        return XDRStatus::exdr3DX
+    }
+    xtc_coord(xd, natoms, r#box, &mut x[..natoms as usize], prec)
+}
+
+/// Write a frame to an xtc file
+pub fn write_xtc(xd: &mut XDRFILE, step: c_int, time: c_float, r#box: &matrix, x: &[rvec], prec: c_float) -> XDRStatus {
+    let Ok(mut natoms) = x.len().try_into() else { return XDRStatus::exdrUINT };
+    let (mut step, mut time) = (step, time);
+    match xtc_header(xd, &mut natoms, &mut step, &mut time, false) {
+        XDRStatus::exdrOK => (),
+        e => return e,
+    }
+    if xd.write_float(r#box.0.as_flattened()) != DIM * DIM {
+        return XDRStatus::exdrFLOAT
+    }
+    if xd.compress_coord_float(flatten(x), prec) != natoms {
+        return XDRStatus::exdr3DX
+    }
+    XDRStatus::exdrOK
+}
+
+/// Write a frame of double precision coordinates to an xtc file. The coordinates are
+/// compressed directly, without converting them to single precision first.
+pub fn write_xtc_double(xd: &mut XDRFILE, step: c_int, time: c_float, r#box: &matrix, x: &[dvec], prec: c_double) -> XDRStatus {
+    let Ok(mut natoms) = x.len().try_into() else { return XDRStatus::exdrUINT };
+    let (mut step, mut time) = (step, time);
+    match xtc_header(xd, &mut natoms, &mut step, &mut time, false) {
+        XDRStatus::exdrOK => (),
+        e => return e,
+    }
+    if xd.write_float(r#box.0.as_flattened()) != DIM * DIM {
+        return XDRStatus::exdrFLOAT
+    }
+    if xd.compress_coord_double(flatten_double(x), prec) != natoms {
+        return XDRStatus::exdr3DX
+    }
+    XDRStatus::exdrOK
+}
+
+/// Read or write the header information of the current frame.
+/// When writing, the 2023 magic number is used if there are more than [`MAX_NATOMS_1995`] atoms,
+/// or if it has been requested for every frame.
+#[allow(non_snake_case)]
+pub fn xtc_header(xd: &mut XDRFILE, natoms: &mut c_int, step: &mut c_int, time: &mut c_float, bRead: bool) -> XDRStatus {
+    if bRead {
+        let mut magic = [0];
+        if xd.read_int(&mut magic) != 1 { return XDRStatus::exdrENDOFFILE }
+        if !is_magic(magic[0]) { return XDRStatus::exdrMAGIC }
+        xd.xtc_2023 = magic[0] == MAGIC_2023;
+        let mut ints = [0; 2];
+        if xd.read_int(&mut ints) != 2 { return XDRStatus::exdrINT }
+        let mut t = [0.];
+        if xd.read_float(&mut t) != 1 { return XDRStatus::exdrFLOAT }
+        [*natoms, *step] = ints;
+        *time = t[0];
+    } else {
+        xd.xtc_2023 = xd.force_xtc_2023 || *natoms > MAX_NATOMS_1995;
+        let magic = if xd.xtc_2023 { MAGIC_2023 } else { MAGIC };
+        if xd.write_int(&[magic, *natoms, *step]) != 3 { return XDRStatus::exdrINT }
+        if xd.write_float(&[*time]) != 1 { return XDRStatus::exdrFLOAT }
+    }
+    XDRStatus::exdrOK
+}
+
+/// Read the box and coordinates of the current frame, after the header.
+/// `x` must have space for at least `natoms` atoms.
+pub fn xtc_coord(xd: &mut XDRFILE, natoms: c_int, r#box: &mut matrix, x: &mut [rvec], prec: &mut c_float) -> XDRStatus {
+    if xd.read_float(r#box.0.as_flattened_mut()) != DIM * DIM {
+        return XDRStatus::exdrFLOAT
+    }
+    let mut ncoord = natoms;
+    if xd.decompress_coord_float(flatten_mut(x), &mut ncoord, prec) != natoms {
+        return XDRStatus::exdr3DX
+    }
+    XDRStatus::exdrOK
+}
+
+/// Read the box and double precision coordinates of the current frame, after the header.
+/// `x` must have space for at least `natoms` atoms.
+pub fn xtc_coord_double(xd: &mut XDRFILE, natoms: c_int, r#box: &mut matrix, x: &mut [dvec], prec: &mut c_double) -> XDRStatus {
+    if xd.read_float(r#box.0.as_flattened_mut()) != DIM * DIM {
+        return XDRStatus::exdrFLOAT
+    }
+    let mut ncoord = natoms;
+    if xd.decompress_coord_double(flatten_double_mut(x), &mut ncoord, prec) != natoms {
+        return XDRStatus::exdr3DX
+    }
+    XDRStatus::exdrOK
+}
+
+/// Skip the box and coordinates of the current frame, after the header, without decompressing
+/// the coordinates.
+///
+/// NOTE: Seeking past the end of the file is not an error, so a truncated frame may not be
+/// detected until the next read.
+pub fn xtc_skip_coord(xd: &mut XDRFILE, natoms: c_int) -> XDRStatus {
+    xtc_coord_info(xd, natoms, &mut matrix::new(), &mut 0., &mut 0)
+}
+
+/// Read the box, precision and number of compressed bytes of the current frame, after the
+/// header, then skip the coordinates without decompressing them.
+/// Frames with 9 atoms or fewer are stored uncompressed, in which case `prec` is set to 0 and
+/// `nbytes` to the size of the raw coordinates.
+///
+/// NOTE: Seeking past the end of the file is not an error, so a truncated frame may not be
+/// detected until the next read.
+pub fn xtc_coord_info(xd: &mut XDRFILE, natoms: c_int, r#box: &mut matrix, prec: &mut c_float, nbytes: &mut u64) -> XDRStatus {
+    if xd.read_float(r#box.0.as_flattened_mut()) != DIM * DIM {
+        return XDRStatus::exdrFLOAT
+    }
+    let mut lsize = [0];
+    if natoms < 0 || xd.read_int(&mut lsize) != 1 || lsize[0] != natoms {
+        return XDRStatus::exdr3DX
+    }
+    if natoms <= 9 {
+        *prec = 0.;
+        *nbytes = natoms as u64 * (DIM * size_of::<c_float>()) as u64;
+    } else {
+        // Skip minint, maxint and smallidx, then read the number of compressed bytes
+        let mut p = [0.];
+        if xd.read_float(&mut p) != 1 {
+            return XDRStatus::exdr3DX
+        }
+        *prec = p[0];
+        let nskip = (2 * DIM + 1) * size_of::<c_int>();
+        if xd.seek(SeekFrom::Current(nskip as i64)) != XDRStatus::exdrOK {
+            return XDRStatus::exdr3DX
+        }
+        let Some(n) = xd.read_nbytes() else { return XDRStatus::exdr3DX };
+        *nbytes = n;
+    }
+    // Compressed data is padded to a multiple of 4 bytes
+    let Ok(nskip) = i64::try_from(nbytes.div_ceil(4) * 4) else { return XDRStatus::exdr3DX };
+    if xd.seek(SeekFrom::Current(nskip)) != XDRStatus::exdrOK {
+        return XDRStatus::exdr3DX
+    }
+    XDRStatus::exdrOK
 }
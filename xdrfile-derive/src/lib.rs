@@ -0,0 +1,276 @@
+//! Derive macros for the `XdrEncode` and `XdrDecode` traits of the `xdrfile` crate.
+//! See the documentation of `xdrfile::codec` for how types are encoded.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    Data, DeriveInput, Expr, Fields, Generics, Ident, Type, parse_macro_input, parse_quote,
+    spanned::Spanned,
+};
+
+/// Derive `xdrfile::codec::XdrEncode`, writing each field in order
+#[proc_macro_derive(XdrEncode, attributes(xdr))]
+pub fn derive_encode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    encode(&input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+/// Derive `xdrfile::codec::XdrDecode`, reading each field in order
+#[proc_macro_derive(XdrDecode, attributes(xdr))]
+pub fn derive_decode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    decode(&input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+/// Options set by `#[xdr(...)]` on a field
+#[derive(Default)]
+struct FieldAttrs {
+    /// Number of elements of a `Vec`, which is then stored without a length
+    fixed: Option<Expr>,
+    /// Store bytes as opaque data rather than an int per byte
+    opaque: bool,
+    /// Number of bytes of zero padding following the field
+    pad: Option<Expr>,
+    /// Don't store the field, and use its default value when decoding
+    skip: bool,
+}
+
+impl FieldAttrs {
+    fn parse(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut result = Self::default();
+        for attr in attrs.iter().filter(|a| a.path().is_ident("xdr")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("fixed") {
+                    result.fixed = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("pad") {
+                    result.pad = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("opaque") {
+                    result.opaque = true;
+                } else if meta.path.is_ident("skip") {
+                    result.skip = true;
+                } else {
+                    return Err(meta.error("expected `fixed`, `opaque`, `pad` or `skip`"))
+                }
+                Ok(())
+            })?;
+        }
+        if result.skip && (result.fixed.is_some() || result.opaque || result.pad.is_some()) {
+            return Err(syn::Error::new(attrs[0].span(), "`skip` can't be combined with other options"))
+        }
+        Ok(result)
+    }
+
+    fn fixed_option(&self) -> TokenStream2 {
+        match &self.fixed {
+            Some(n) => quote!(::core::option::Option::Some(#n)),
+            None => quote!(::core::option::Option::None),
+        }
+    }
+}
+
+/// A field, with the expression or binding used to access it
+struct Field {
+    attrs: FieldAttrs,
+    ty: Type,
+    binding: Ident,
+    member: TokenStream2,
+}
+
+fn fields(fields: &Fields) -> syn::Result<Vec<Field>> {
+    fields.iter().enumerate().map(|(i, f)| {
+        let member = match &f.ident {
+            Some(ident) => quote!(#ident),
+            None => {
+                let index = syn::Index::from(i);
+                quote!(#index)
+            }
+        };
+        Ok(Field {
+            attrs: FieldAttrs::parse(&f.attrs)?,
+            ty: f.ty.clone(),
+            binding: format_ident!("__field{}", i),
+            member,
+        })
+    }).collect()
+}
+
+/// Statements which write each field from its binding, which is a reference
+fn encode_fields(fields: &[Field]) -> TokenStream2 {
+    let mut tokens = TokenStream2::new();
+    for field in fields {
+        let binding = &field.binding;
+        let attrs = &field.attrs;
+        if attrs.skip {
+            continue
+        }
+        tokens.extend(if attrs.opaque {
+            let fixed = attrs.fixed_option();
+            quote!(::xdrfile::codec::Opaque::encode_opaque(#binding, #fixed, __xdr_file)?;)
+        } else if let Some(n) = &attrs.fixed {
+            quote!(::xdrfile::codec::encode_fixed(#binding, #n, __xdr_file)?;)
+        } else {
+            quote!(::xdrfile::codec::XdrEncode::encode(#binding, __xdr_file)?;)
+        });
+        if let Some(n) = &attrs.pad {
+            tokens.extend(quote!(::xdrfile::codec::encode_padding(#n, __xdr_file)?;));
+        }
+    }
+    tokens
+}
+
+/// Statements which read each field into its binding
+fn decode_fields(fields: &[Field]) -> TokenStream2 {
+    let mut tokens = TokenStream2::new();
+    for field in fields {
+        let binding = &field.binding;
+        let ty = &field.ty;
+        let attrs = &field.attrs;
+        tokens.extend(if attrs.skip {
+            quote!(let #binding = ::core::default::Default::default();)
+        } else if attrs.opaque {
+            let fixed = attrs.fixed_option();
+            quote!(let #binding = <#ty as ::xdrfile::codec::Opaque>::decode_opaque(#fixed, __xdr_file)?;)
+        } else if let Some(n) = &attrs.fixed {
+            quote!(let #binding: #ty = ::xdrfile::codec::decode_fixed(#n, __xdr_file)?;)
+        } else {
+            quote!(let #binding = <#ty as ::xdrfile::codec::XdrDecode>::decode(__xdr_file)?;)
+        });
+        if let Some(n) = &attrs.pad {
+            tokens.extend(quote!(::xdrfile::codec::decode_padding(#n, __xdr_file)?;));
+        }
+    }
+    tokens
+}
+
+/// Pattern or constructor for `path` from the bindings of its fields
+fn construct(path: TokenStream2, fields: &Fields, bound: &[Field]) -> TokenStream2 {
+    let members = bound.iter().map(|f| &f.member);
+    let bindings = bound.iter().map(|f| &f.binding);
+    match fields {
+        Fields::Unit => path,
+        _ => quote!(#path { #(#members: #bindings),* }),
+    }
+}
+
+/// Add `bound` to every type parameter
+fn add_bounds(generics: &Generics, bound: syn::TypeParamBound) -> Generics {
+    let mut generics = generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(bound.clone());
+    }
+    generics
+}
+
+/// Discriminant of each variant, which follows the rules for Rust enums: an explicit
+/// discriminant if there is one, or one more than the previous variant otherwise
+fn discriminants(data: &syn::DataEnum) -> Vec<TokenStream2> {
+    let mut prev: Option<TokenStream2> = None;
+    data.variants.iter().map(|v| {
+        let tag = match (&v.discriminant, &prev) {
+            (Some((_, expr)), _) => quote!((#expr) as i32),
+            (None, Some(prev)) => quote!(#prev + 1),
+            (None, None) => quote!(0i32),
+        };
+        prev = Some(tag.clone());
+        tag
+    }).collect()
+}
+
+fn encode(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let generics = add_bounds(&input.generics, parse_quote!(::xdrfile::codec::XdrEncode));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let bound = fields(&data.fields)?;
+            let pattern = construct(quote!(Self), &data.fields, &bound);
+            let encode = encode_fields(&bound);
+            quote! {
+                let #pattern = self;
+                #encode
+            }
+        }
+        Data::Enum(data) => {
+            let mut arms = TokenStream2::new();
+            for (variant, tag) in data.variants.iter().zip(discriminants(data)) {
+                let ident = &variant.ident;
+                let bound = fields(&variant.fields)?;
+                let pattern = construct(quote!(Self::#ident), &variant.fields, &bound);
+                let encode = encode_fields(&bound);
+                arms.extend(quote! {
+                    #pattern => {
+                        ::xdrfile::codec::XdrEncode::encode(&(#tag), __xdr_file)?;
+                        #encode
+                    }
+                });
+            }
+            if data.variants.is_empty() {
+                quote!(match *self {})
+            } else {
+                quote!(match self { #arms })
+            }
+        }
+        Data::Union(_) => return Err(syn::Error::new(input.span(), "XdrEncode can't be derived for unions")),
+    };
+    Ok(quote! {
+        impl #impl_generics ::xdrfile::codec::XdrEncode for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn encode<__MODE: ::xdrfile::XDRAccessMode + ::xdrfile::access_mode::Writable>(
+                &self,
+                __xdr_file: &mut ::xdrfile::XDRFile<__MODE>,
+            ) -> ::core::result::Result<(), ::xdrfile::Error> {
+                #body
+                ::core::result::Result::Ok(())
+            }
+        }
+    })
+}
+
+fn decode(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let generics = add_bounds(&input.generics, parse_quote!(::xdrfile::codec::XdrDecode));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let bound = fields(&data.fields)?;
+            let decode = decode_fields(&bound);
+            let value = construct(quote!(Self), &data.fields, &bound);
+            quote! {
+                #decode
+                ::core::result::Result::Ok(#value)
+            }
+        }
+        Data::Enum(data) => {
+            let mut arms = TokenStream2::new();
+            for (variant, tag) in data.variants.iter().zip(discriminants(data)) {
+                let ident = &variant.ident;
+                let bound = fields(&variant.fields)?;
+                let decode = decode_fields(&bound);
+                let value = construct(quote!(Self::#ident), &variant.fields, &bound);
+                arms.extend(quote! {
+                    if __tag == #tag {
+                        #decode
+                        return ::core::result::Result::Ok(#value)
+                    }
+                });
+            }
+            let name = name.to_string();
+            quote! {
+                let __tag = <i32 as ::xdrfile::codec::XdrDecode>::decode(__xdr_file)?;
+                #arms
+                ::core::result::Result::Err(::xdrfile::codec::unknown_variant(#name, __tag))
+            }
+        }
+        Data::Union(_) => return Err(syn::Error::new(input.span(), "XdrDecode can't be derived for unions")),
+    };
+    Ok(quote! {
+        impl #impl_generics ::xdrfile::codec::XdrDecode for #name #ty_generics #where_clause {
+            fn decode(
+                __xdr_file: &mut ::xdrfile::XDRFile<::xdrfile::access_mode::Read>,
+            ) -> ::core::result::Result<Self, ::xdrfile::Error> {
+                #body
+            }
+        }
+    })
+}